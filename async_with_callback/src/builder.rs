@@ -0,0 +1,243 @@
+use std::{any::Any, env, fmt, rc::Rc, sync::Arc, thread, time::Duration};
+
+use crate::logger::{self, Logger};
+use crate::runtime::Runtime;
+use crate::scheduler::{OverflowPolicy, SchedulingPolicy, ThreadPoolMode};
+
+/// The environment variable which overrides the default number of worker
+/// threads, like libuv's `UV_THREADPOOL_SIZE`.
+pub const THREADPOOL_SIZE_ENV: &str = "ASYNC_THREADPOOL_SIZE";
+
+/// Called on a worker thread with the id of the worker, right after it started
+/// or right before it stops.
+pub type WorkerHook = Arc<dyn Fn(usize) + Send + Sync + 'static>;
+
+/// Called on the main thread with the payload of a panic which was not
+/// caught by a callback or a task.
+pub type PanicHook = Rc<dyn Fn(Box<dyn Any + Send>) + 'static>;
+
+/// Configures and creates a `Runtime`.
+///
+/// ```no_run
+/// use async_with_callback::runtime::Runtime;
+///
+/// let runtime = Runtime::builder()
+///     .worker_threads(2)
+///     .thread_name("fs-worker")
+///     .on_thread_start(|id| println!("worker {} started", id))
+///     .build();
+/// ```
+pub struct RuntimeBuilder {
+    pub(crate) worker_threads: Option<usize>,
+    pub(crate) thread_name: String,
+    pub(crate) thread_stack_size: Option<usize>,
+    pub(crate) on_thread_start: Option<WorkerHook>,
+    pub(crate) on_thread_stop: Option<WorkerHook>,
+    pub(crate) logger: Arc<dyn Logger>,
+    pub(crate) scheduling_policy: SchedulingPolicy,
+    pub(crate) thread_pool_mode: ThreadPoolMode,
+    pub(crate) reserved_io_workers: usize,
+    pub(crate) max_queued_work: Option<usize>,
+    pub(crate) overflow_policy: OverflowPolicy,
+    pub(crate) on_uncaught_panic: Option<PanicHook>,
+    pub(crate) shutdown_timeout: Option<Duration>,
+}
+
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuntimeBuilder {
+    pub fn new() -> Self {
+        RuntimeBuilder {
+            worker_threads: None,
+            thread_name: "async-worker".to_string(),
+            thread_stack_size: None,
+            on_thread_start: None,
+            on_thread_stop: None,
+            logger: logger::from_env(),
+            scheduling_policy: SchedulingPolicy::default(),
+            thread_pool_mode: ThreadPoolMode::default(),
+            reserved_io_workers: 0,
+            max_queued_work: None,
+            overflow_policy: OverflowPolicy::default(),
+            on_uncaught_panic: None,
+            shutdown_timeout: None,
+        }
+    }
+
+    /// Sets the number of threads in the thread pool. If not set, the value of
+    /// `ASYNC_THREADPOOL_SIZE` is used, and if that is missing the number of
+    /// available CPUs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn worker_threads(mut self, n: usize) -> Self {
+        assert!(n > 0, "the thread pool needs at least one worker thread");
+        self.worker_threads = Some(n);
+        self
+    }
+
+    /// Sets the name of the worker threads. Each worker is named
+    /// `<name>-<id>`.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = name.into();
+        self
+    }
+
+    /// Sets the stack size of the worker threads in bytes.
+    pub fn thread_stack_size(mut self, size: usize) -> Self {
+        self.thread_stack_size = Some(size);
+        self
+    }
+
+    /// Runs `f` on every worker thread before it takes its first task. A panic
+    /// in `f` kills the worker, which is then replaced by a new one. After
+    /// three panics in a row the worker is given up, and the `RunReport` holds
+    /// `RunError::WorkerStartFailed`.
+    pub fn on_thread_start(mut self, f: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_thread_start = Some(Arc::new(f));
+        self
+    }
+
+    /// Runs `f` on every worker thread after it received its last task.
+    pub fn on_thread_stop(mut self, f: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_thread_stop = Some(Arc::new(f));
+        self
+    }
+
+    /// Reports what the event loop, the thread pool and the epoll thread are
+    /// doing to `logger`. The default logger is configured by `ASYNC_LOG`, and
+    /// discards everything if that is not set.
+    pub fn logger(mut self, logger: Arc<dyn Logger>) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    /// Sets the order in which queued work is handed to the thread pool. The
+    /// default is `SchedulingPolicy::Fifo`.
+    pub fn scheduling_policy(mut self, policy: SchedulingPolicy) -> Self {
+        self.scheduling_policy = policy;
+        self
+    }
+
+    /// Sets how queued work gets to the worker threads. The default is
+    /// `ThreadPoolMode::Dispatcher`.
+    ///
+    /// With `ThreadPoolMode::WorkStealing` the scheduling policy orders the
+    /// queue of each worker, not the work of the whole pool, since submitted
+    /// work is spread over the queues and idle workers start it right away.
+    pub fn thread_pool_mode(mut self, mode: ThreadPoolMode) -> Self {
+        self.thread_pool_mode = mode;
+        self
+    }
+
+    /// Keeps `n` worker threads free of CPU bound work, like `Fibonacchi` or
+    /// `spawn_blocking`, so file system work always finds a worker within the
+    /// time one file system task takes. By default every worker takes every
+    /// kind of work.
+    ///
+    /// At least one worker is left for CPU bound work, so with a single worker
+    /// thread this has no effect.
+    pub fn reserved_io_workers(mut self, n: usize) -> Self {
+        self.reserved_io_workers = n;
+        self
+    }
+
+    /// Limits the number of tasks waiting for a free thread in the thread pool.
+    /// By default the queue is unbounded. What happens to work which doesn't
+    /// fit is decided by `overflow_policy`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn max_queued_work(mut self, n: usize) -> Self {
+        assert!(
+            n > 0,
+            "the thread pool queue needs room for at least one task"
+        );
+        self.max_queued_work = Some(n);
+        self
+    }
+
+    /// Sets what happens to new work when the queue is full. The default is
+    /// `OverflowPolicy::Reject`.
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Calls `f` with the payload of every panic in a callback or a task, like
+    /// Node's `uncaughtException` event, and keeps the loop running. A task
+    /// which panicked doesn't call its callback.
+    ///
+    /// Without this hook the panic is resumed and unwinds out of
+    /// `Runtime::run`.
+    pub fn on_uncaught_panic(mut self, f: impl Fn(Box<dyn Any + Send>) + 'static) -> Self {
+        self.on_uncaught_panic = Some(Rc::new(f));
+        self
+    }
+
+    /// Limits how long `Runtime::shutdown` waits for work which is already
+    /// running. Callbacks of work which didn't finish in time are never
+    /// called, and the worker threads still running it are detached instead of
+    /// joined. By default the shutdown waits for all running work.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// The number of worker threads the runtime will be started with.
+    pub fn worker_count(&self) -> usize {
+        self.worker_threads
+            .or_else(|| {
+                env::var(THREADPOOL_SIZE_ENV)
+                    .ok()
+                    .and_then(|n| parse_worker_count(&n))
+            })
+            .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(4)
+    }
+
+    pub fn build(self) -> Runtime {
+        Runtime::from_builder(self)
+    }
+}
+
+/// Parses the value of `ASYNC_THREADPOOL_SIZE`. Anything which isn't a positive
+/// number is ignored.
+fn parse_worker_count(value: &str) -> Option<usize> {
+    value.trim().parse().ok().filter(|n| *n > 0)
+}
+
+impl fmt::Debug for RuntimeBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RuntimeBuilder")
+            .field("worker_threads", &self.worker_threads)
+            .field("thread_name", &self.thread_name)
+            .field("thread_stack_size", &self.thread_stack_size)
+            .field("on_thread_start", &self.on_thread_start.is_some())
+            .field("on_thread_stop", &self.on_thread_stop.is_some())
+            .field("scheduling_policy", &self.scheduling_policy)
+            .field("thread_pool_mode", &self.thread_pool_mode)
+            .field("reserved_io_workers", &self.reserved_io_workers)
+            .field("max_queued_work", &self.max_queued_work)
+            .field("overflow_policy", &self.overflow_policy)
+            .field("on_uncaught_panic", &self.on_uncaught_panic.is_some())
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish()
+    }
+}
+
+#[test]
+fn test_parse_worker_count() {
+    assert_eq!(parse_worker_count("3"), Some(3));
+    assert_eq!(parse_worker_count(" 8\n"), Some(8));
+    assert_eq!(parse_worker_count("0"), None);
+    assert_eq!(parse_worker_count("-1"), None);
+    assert_eq!(parse_worker_count("many"), None);
+    assert_eq!(parse_worker_count(""), None);
+}
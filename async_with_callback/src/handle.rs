@@ -0,0 +1,244 @@
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt,
+    rc::{Rc, Weak},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+};
+
+use crate::pollevent::PollEvent;
+use crate::runtime::{HandleKind, Runtime};
+
+thread_local! {
+    // The runtime which is currently running on this thread, if any. Set by
+    // `Runtime::run` for as long as the loop runs.
+    static CURRENT: RefCell<Option<Weak<RefCell<Runtime>>>> = const { RefCell::new(None) };
+}
+
+/// Returned when an API which needs a runtime is used outside of
+/// `Runtime::run`, or after the runtime it belonged to has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoRuntimeError;
+
+impl fmt::Display for NoRuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "no runtime is running on this thread, \
+             this must be called from within `Runtime::run`"
+        )
+    }
+}
+
+impl Error for NoRuntimeError {}
+
+/// Returned by `LoopSender::send` when the runtime the sender belongs to has
+/// finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopClosedError;
+
+impl fmt::Display for LoopClosedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the event loop has finished")
+    }
+}
+
+impl Error for LoopClosedError {}
+
+/// A handle to the runtime running on the current thread. Tasks like `Fs` and
+/// `Timeout` use it to reach the event loop they were started from.
+///
+/// The handle does not keep the runtime alive. Once `Runtime::run` has
+/// returned, every call through the handle fails with `NoRuntimeError`.
+#[derive(Clone)]
+pub struct RuntimeHandle {
+    inner: Weak<RefCell<Runtime>>,
+}
+
+impl RuntimeHandle {
+    /// Returns a handle to the runtime running on this thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of `Runtime::run`. Use `try_current` to handle
+    /// that case.
+    pub fn current() -> Self {
+        Self::try_current().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Returns a handle to the runtime running on this thread, or an error if
+    /// there is none.
+    pub fn try_current() -> Result<Self, NoRuntimeError> {
+        CURRENT.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .filter(|rt| rt.strong_count() > 0)
+                .map(|rt| RuntimeHandle { inner: rt.clone() })
+                .ok_or(NoRuntimeError)
+        })
+    }
+
+    /// Returns `true` as long as the runtime this handle belongs to is running.
+    pub fn is_running(&self) -> bool {
+        self.inner.strong_count() > 0
+    }
+
+    /// The number of tasks waiting for a free thread in the thread pool of the
+    /// runtime, or 0 if it isn't running anymore.
+    pub fn queue_depth(&self) -> usize {
+        self.with(|rt| rt.queue_depth()).unwrap_or(0)
+    }
+
+    /// The number of active handles of `kind` which keep the runtime alive, or
+    /// 0 if it isn't running anymore.
+    pub fn active_handles(&self, kind: HandleKind) -> usize {
+        self.with(|rt| rt.active_handles(kind)).unwrap_or(0)
+    }
+
+    /// Starts a graceful shutdown of the runtime, see `Runtime::shutdown`.
+    pub fn shutdown(&self) -> Result<(), NoRuntimeError> {
+        self.with(|rt| rt.begin_shutdown())
+    }
+
+    /// Returns a `LoopSender` for the runtime, see `Runtime::loop_sender`.
+    pub fn loop_sender(&self) -> Result<LoopSender, NoRuntimeError> {
+        self.with(|rt| rt.loop_sender())
+    }
+
+    /// Runs `f` with exclusive access to the runtime. `f` must not call back
+    /// into user code, since that could try to access the runtime again.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut Runtime) -> R) -> Result<R, NoRuntimeError> {
+        let rt = self.inner.upgrade().ok_or(NoRuntimeError)?;
+        let mut rt = rt.borrow_mut();
+        Ok(f(&mut rt))
+    }
+}
+
+/// Posts callbacks into the event loop from any thread, like libuv's
+/// `uv_async_send`. The loop keeps running while a referenced sender exists,
+/// and a loop which is waiting for events wakes up to run the callback.
+///
+/// ```no_run
+/// use async_with_callback::{runtime::Runtime, task::Timeout};
+/// use std::thread;
+///
+/// let runtime = Runtime::new();
+/// let sender = runtime.loop_sender();
+/// thread::spawn(move || {
+///     let answer = 42;
+///     // Runs on the loop thread, so it can use the runtime
+///     sender
+///         .send(move || {
+///             Timeout::set_timeout(100, move || println!("{}", answer));
+///         })
+///         .unwrap();
+/// });
+/// runtime.run(|| ());
+/// ```
+pub struct LoopSender {
+    sender: Sender<PollEvent>,
+    // The number of referenced senders of the runtime which are alive
+    count: Arc<AtomicUsize>,
+    // Whether this sender keeps the loop alive
+    referenced: AtomicBool,
+}
+
+impl LoopSender {
+    pub(crate) fn new(sender: Sender<PollEvent>, count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        LoopSender {
+            sender,
+            count,
+            referenced: AtomicBool::new(true),
+        }
+    }
+
+    /// Stops this sender from keeping the loop alive. Callbacks sent through
+    /// it still run as long as the loop is running.
+    pub fn unref(&self) {
+        if self.referenced.swap(false, Ordering::SeqCst) {
+            self.release();
+        }
+    }
+
+    /// Makes this sender keep the loop alive again after `unref`.
+    pub fn ref_(&self) {
+        if !self.referenced.swap(true, Ordering::SeqCst) {
+            self.count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns `true` if this sender keeps the loop alive.
+    pub fn has_ref(&self) -> bool {
+        self.referenced.load(Ordering::SeqCst)
+    }
+
+    fn release(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // The loop may be waiting for nothing but this sender
+            let _ = self.sender.send(PollEvent::Unreferenced);
+        }
+    }
+
+    /// Queues `f` to run on the loop thread, in the poll phase. Callbacks sent
+    /// through the same sender run in the order they were sent.
+    pub fn send(&self, f: impl FnOnce() + Send + 'static) -> Result<(), LoopClosedError> {
+        self.sender
+            .send(PollEvent::Posted(Box::new(f)))
+            .map_err(|_| LoopClosedError)
+    }
+}
+
+impl Clone for LoopSender {
+    fn clone(&self) -> Self {
+        LoopSender::new(self.sender.clone(), self.count.clone())
+    }
+}
+
+impl Drop for LoopSender {
+    fn drop(&mut self) {
+        self.unref();
+    }
+}
+
+impl fmt::Debug for LoopSender {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LoopSender")
+            .field("referenced", &self.has_ref())
+            .field("senders", &self.count.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+/// Runs `f` with the runtime running on the current thread.
+///
+/// # Panics
+///
+/// Panics with a `NoRuntimeError` message if there is no runtime.
+pub(crate) fn with_current<R>(f: impl FnOnce(&mut Runtime) -> R) -> R {
+    RuntimeHandle::current()
+        .with(f)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Makes `rt` the current runtime of this thread until the guard is dropped.
+pub(crate) fn enter(rt: &Rc<RefCell<Runtime>>) -> EnterGuard {
+    let previous = CURRENT.with(|current| current.replace(Some(Rc::downgrade(rt))));
+    EnterGuard { previous }
+}
+
+pub(crate) struct EnterGuard {
+    previous: Option<Weak<RefCell<Runtime>>>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
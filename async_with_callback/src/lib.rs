@@ -1,5 +1,9 @@
-pub mod ioresult;
+pub mod builder;
+pub mod handle;
+pub mod logger;
 pub mod nodethread;
 pub mod pollevent;
 pub mod runtime;
+pub mod scheduler;
 pub mod task;
+pub mod timer;
@@ -0,0 +1,310 @@
+use std::{
+    env, fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The environment variable read by `from_env`. It accepts a level name
+/// (`error`, `warn`, `info`, `debug`, `trace`) for human readable output, or
+/// `json` / `json:<level>` for JSON lines.
+pub const LOG_ENV: &str = "ASYNC_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            other => Err(format!("unknown log level: {}", other)),
+        }
+    }
+}
+
+/// A single log event of the event loop. `target` names the part of the
+/// runtime which emitted it (`runtime`, `threadpool`, `epoll`, `reactor`),
+/// the remaining fields are only set when they apply to the event.
+#[derive(Debug, Clone)]
+pub struct Record<'a> {
+    pub level: Level,
+    pub target: &'static str,
+    pub message: &'a str,
+    pub tick: Option<usize>,
+    pub callback_id: Option<usize>,
+    pub timer_id: Option<u64>,
+    pub thread_id: Option<usize>,
+    pub task_kind: Option<&'a str>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(level: Level, target: &'static str, message: &'a str) -> Self {
+        Record {
+            level,
+            target,
+            message,
+            tick: None,
+            callback_id: None,
+            timer_id: None,
+            thread_id: None,
+            task_kind: None,
+        }
+    }
+
+    pub fn tick(mut self, tick: usize) -> Self {
+        self.tick = Some(tick);
+        self
+    }
+
+    pub fn callback_id(mut self, callback_id: usize) -> Self {
+        self.callback_id = Some(callback_id);
+        self
+    }
+
+    pub fn timer_id(mut self, timer_id: u64) -> Self {
+        self.timer_id = Some(timer_id);
+        self
+    }
+
+    pub fn thread_id(mut self, thread_id: usize) -> Self {
+        self.thread_id = Some(thread_id);
+        self
+    }
+
+    pub fn task_kind(mut self, task_kind: &'a str) -> Self {
+        self.task_kind = Some(task_kind);
+        self
+    }
+
+    /// Writes the record as a single line of JSON, without the newline.
+    pub fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        let ts_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+
+        write!(out, "{{\"ts_ms\":{},\"level\":\"{}\",", ts_ms, self.level)?;
+        write!(out, "\"target\":{},", JsonStr(self.target))?;
+        write!(out, "\"message\":{}", JsonStr(self.message))?;
+        if let Some(tick) = self.tick {
+            write!(out, ",\"tick\":{}", tick)?;
+        }
+        if let Some(callback_id) = self.callback_id {
+            write!(out, ",\"callback_id\":{}", callback_id)?;
+        }
+        if let Some(timer_id) = self.timer_id {
+            write!(out, ",\"timer_id\":{}", timer_id)?;
+        }
+        if let Some(thread_id) = self.thread_id {
+            write!(out, ",\"thread_id\":{}", thread_id)?;
+        }
+        if let Some(task_kind) = self.task_kind {
+            write!(out, ",\"task_kind\":{}", JsonStr(task_kind))?;
+        }
+        write!(out, "}}")
+    }
+}
+
+impl<'a> fmt::Display for Record<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:5} {}]",
+            self.level.as_str().to_uppercase(),
+            self.target
+        )?;
+        if let Some(tick) = self.tick {
+            write!(f, " tick={}", tick)?;
+        }
+        if let Some(thread_id) = self.thread_id {
+            write!(f, " thread={}", thread_id)?;
+        }
+        if let Some(callback_id) = self.callback_id {
+            write!(f, " callback={}", callback_id)?;
+        }
+        if let Some(timer_id) = self.timer_id {
+            write!(f, " timer={}", timer_id)?;
+        }
+        if let Some(task_kind) = self.task_kind {
+            write!(f, " kind={}", task_kind)?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+struct JsonStr<'a>(&'a str);
+
+impl<'a> fmt::Display for JsonStr<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// Receives the log events of the runtime. A logger is shared between the main
+/// loop, the worker threads and the epoll thread, so it needs to be `Send` and
+/// `Sync`.
+pub trait Logger: Send + Sync {
+    /// Returns `false` if records of this level are discarded anyway, so the
+    /// caller can skip building them.
+    fn enabled(&self, level: Level) -> bool;
+
+    fn log(&self, record: &Record);
+}
+
+/// Logs `record` if `logger` is interested in its level.
+pub fn log(logger: &dyn Logger, record: Record) {
+    if logger.enabled(record.level) {
+        logger.log(&record);
+    }
+}
+
+/// The default logger. Discards everything.
+#[derive(Debug, Default)]
+pub struct NoopLogger;
+
+impl Logger for NoopLogger {
+    fn enabled(&self, _level: Level) -> bool {
+        false
+    }
+
+    fn log(&self, _record: &Record) {}
+}
+
+/// Prints human readable lines to stdout.
+#[derive(Debug)]
+pub struct TextLogger {
+    level: Level,
+}
+
+impl TextLogger {
+    pub fn new(level: Level) -> Self {
+        TextLogger { level }
+    }
+}
+
+impl Logger for TextLogger {
+    fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        println!("{}", record);
+    }
+}
+
+/// Writes one JSON object per line to `out`, for offline analysis of the
+/// event loop.
+pub struct JsonLogger<W: Write + Send> {
+    level: Level,
+    out: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLogger<W> {
+    pub fn new(out: W, level: Level) -> Self {
+        JsonLogger {
+            level,
+            out: Mutex::new(out),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<W: Write + Send> Logger for JsonLogger<W> {
+    fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = record.write_json(&mut *out).and_then(|_| writeln!(out));
+    }
+}
+
+/// Creates a logger as configured by the `ASYNC_LOG` environment variable.
+/// Returns a `NoopLogger` if the variable is missing or can't be parsed.
+pub fn from_env() -> Arc<dyn Logger> {
+    let value = match env::var(LOG_ENV) {
+        Ok(value) => value,
+        Err(_) => return Arc::new(NoopLogger),
+    };
+
+    let mut parts = value.splitn(2, ':');
+    match (parts.next().unwrap_or(""), parts.next()) {
+        ("json", level) => {
+            let level = level.and_then(|l| l.parse().ok()).unwrap_or(Level::Trace);
+            Arc::new(JsonLogger::new(io::stderr(), level))
+        }
+        (level, None) => match level.parse() {
+            Ok(level) => Arc::new(TextLogger::new(level)),
+            Err(_) => Arc::new(NoopLogger),
+        },
+        _ => Arc::new(NoopLogger),
+    }
+}
+
+#[test]
+fn test_json_record() {
+    let logger = JsonLogger::new(vec![], Level::Debug);
+    let record = Record::new(Level::Debug, "threadpool", "say \"hi\"\n")
+        .tick(3)
+        .thread_id(1)
+        .callback_id(7)
+        .task_kind("FileRead");
+    log(&logger, record);
+    log(&logger, Record::new(Level::Trace, "threadpool", "filtered"));
+
+    let out = String::from_utf8(logger.into_inner()).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("{\"ts_ms\":"));
+    assert!(lines[0].ends_with(
+        "\"level\":\"debug\",\"target\":\"threadpool\",\"message\":\"say \\\"hi\\\"\\n\",\
+         \"tick\":3,\"callback_id\":7,\"thread_id\":1,\"task_kind\":\"FileRead\"}"
+    ));
+}
@@ -1,9 +1,182 @@
-use std::{sync::mpsc::Sender, thread};
+use std::{
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
 
-use crate::task;
+use crate::builder::WorkerHook;
+use crate::logger::{self, Level, Logger, Record};
+use crate::pollevent::PollEvent;
+use crate::scheduler::{Lane, SharedQueue, ThreadPoolMode};
+use crate::task::{self, ThreadPoolTaskKind};
 
 #[derive(Debug)]
 pub struct NodeThread {
     pub(crate) handle: thread::JoinHandle<()>,
-    pub(crate) sender: Sender<task::Task>,
+    // Only set with `ThreadPoolMode::Dispatcher`, work stealing workers take
+    // their work from the queues
+    pub(crate) sender: Option<Sender<task::Task>>,
+}
+
+/// Everything needed to start a worker thread, kept by the runtime so it can
+/// replace a worker which died.
+pub(crate) struct WorkerConfig {
+    pub(crate) thread_name: String,
+    pub(crate) thread_stack_size: Option<usize>,
+    pub(crate) on_thread_start: Option<WorkerHook>,
+    pub(crate) on_thread_stop: Option<WorkerHook>,
+    pub(crate) logger: Arc<dyn Logger>,
+    pub(crate) event_sender: Sender<PollEvent>,
+    pub(crate) mode: ThreadPoolMode,
+    pub(crate) queue: Arc<SharedQueue>,
+}
+
+/// Where a worker gets its work from.
+enum Source {
+    Channel(Receiver<task::Task>),
+    /// The pool's queues and the index of the worker's own queue.
+    Stealing(Arc<SharedQueue>, usize),
+}
+
+impl Source {
+    fn next(&self) -> Option<task::Task> {
+        match self {
+            Source::Channel(receiver) => receiver.recv().ok(),
+            Source::Stealing(queue, worker) => queue.take(*worker),
+        }
+    }
+
+    /// Tells the pool's queues that the worker is done with work of `lane`.
+    /// The loop keeps track of dispatched work itself.
+    fn finished(&self, lane: Lane) {
+        if let Source::Stealing(queue, _) = self {
+            queue.finished(lane);
+        }
+    }
+}
+
+impl NodeThread {
+    /// Starts the worker `thread_id`. Panics of a task are caught and sent to
+    /// the main loop as the result of the task, so the worker survives them.
+    pub(crate) fn spawn(thread_id: usize, config: &WorkerConfig) -> NodeThread {
+        let (sender, source) = match config.mode {
+            ThreadPoolMode::Dispatcher => {
+                let (sender, receiver) = channel::<task::Task>();
+                (Some(sender), Source::Channel(receiver))
+            }
+            ThreadPoolMode::WorkStealing => {
+                (None, Source::Stealing(config.queue.clone(), thread_id))
+            }
+        };
+        let event_sender = config.event_sender.clone();
+        let logger = config.logger.clone();
+        let on_thread_start = config.on_thread_start.clone();
+        let on_thread_stop = config.on_thread_stop.clone();
+
+        let mut thread_builder =
+            thread::Builder::new().name(format!("{}-{}", config.thread_name, thread_id));
+        if let Some(stack_size) = config.thread_stack_size {
+            thread_builder = thread_builder.stack_size(stack_size);
+        }
+
+        let handle = thread_builder.spawn(move || {
+            let mut guard = WorkerGuard {
+                thread_id,
+                started: false,
+                running: None,
+                source,
+                event_sender,
+            };
+
+            if let Some(on_thread_start) = on_thread_start {
+                on_thread_start(thread_id);
+            }
+            guard.started = true;
+
+            while let Some(task) = guard.source.next() {
+                logger::log(
+                    &*logger,
+                    Record::new(Level::Debug, "threadpool", "received a task")
+                        .thread_id(thread_id)
+                        .callback_id(task.callback_id)
+                        .task_kind(task.kind.name()),
+                );
+
+                if let ThreadPoolTaskKind::Close = task.kind {
+                    logger::log(
+                        &*logger,
+                        Record::new(Level::Debug, "threadpool", "closed").thread_id(thread_id),
+                    );
+                    break;
+                }
+
+                let lane = task.kind.lane();
+                guard.running = Some((task.callback_id, lane));
+                task::set_cancel_flag(Some(task.cancelled.clone()));
+                let res = panic::catch_unwind(AssertUnwindSafe(task.task));
+                task::set_cancel_flag(None);
+                let message = match res {
+                    Ok(_) => "finished running a task",
+                    Err(_) => "task panicked",
+                };
+                logger::log(
+                    &*logger,
+                    Record::new(Level::Debug, "threadpool", message)
+                        .thread_id(thread_id)
+                        .callback_id(task.callback_id)
+                        .task_kind(task.kind.name()),
+                );
+
+                guard.running = None;
+                guard.source.finished(lane);
+                let event = PollEvent::Threadpool((thread_id, task.callback_id, res));
+                // The loop is gone if it stopped waiting for this task
+                let _ = guard.event_sender.send(event);
+            }
+
+            if let Some(on_thread_stop) = on_thread_stop {
+                on_thread_stop(thread_id);
+            }
+        });
+        let handle = handle.expect("Error spawning threadpool thread");
+
+        NodeThread { handle, sender }
+    }
+}
+
+/// Tells the main loop when its worker thread dies, so the worker can be
+/// replaced. Task panics are caught, but a panicking hook or logger still
+/// unwinds the whole thread.
+struct WorkerGuard {
+    thread_id: usize,
+    // Set once `on_thread_start` returned
+    started: bool,
+    // The callback id and the lane of the task the worker is running
+    running: Option<(usize, Lane)>,
+    source: Source,
+    event_sender: Sender<PollEvent>,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // The loop takes a task which was sent but not started yet out of
+            // the channel itself, so it can't get lost in between. It runs on
+            // the new worker instead.
+            let queued = match &mut self.source {
+                Source::Channel(receiver) => Some(mem::replace(receiver, channel().1)),
+                Source::Stealing(..) => None,
+            };
+            let running = self.running.map(|(callback_id, lane)| {
+                self.source.finished(lane);
+                callback_id
+            });
+            let event = PollEvent::WorkerDied((self.thread_id, self.started, running, queued));
+            let _ = self.event_sender.send(event);
+        }
+    }
 }
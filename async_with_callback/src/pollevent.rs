@@ -1,10 +1,23 @@
-use crate::ioresult::IOResult;
+use std::{sync::mpsc::Receiver, thread};
+
+use crate::runtime::Payload;
+use crate::task::Task;
 
 pub enum PollEvent {
     /// An event from the `threadpool` with a tuple containing the `thread id`,
     /// the `callback_id` and the data which the we expect to process in our
-    /// callback
-    Threadpool((usize, usize, IOResult)),
+    /// callback, or the payload of the panic if the task panicked
+    Threadpool((usize, usize, thread::Result<Payload>)),
     Epoll(usize),
     Timeout,
+    /// A callback sent through a `LoopSender`, to run on the loop
+    Posted(Box<dyn FnOnce() + Send>),
+    /// The last referenced `LoopSender` was dropped or unreferenced, so the
+    /// loop may be done
+    Unreferenced,
+    /// A worker thread died, with its `thread id`, whether its
+    /// `on_thread_start` hook had returned, the `callback_id` of the task it
+    /// was running and the channel it received tasks on, which may still hold
+    /// a task it didn't start
+    WorkerDied((usize, bool, Option<usize>, Option<Receiver<Task>>)),
 }
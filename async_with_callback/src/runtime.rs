@@ -1,129 +1,334 @@
 use std::{
-    collections::{BTreeMap, HashMap},
-    io,
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt, io, mem,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
     sync::{
-        mpsc::{channel, Receiver},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, SendError, Sender},
+        Arc,
     },
     thread,
     time::{Duration, Instant},
 };
 
-use crate::ioresult::IOResult;
-use crate::nodethread::NodeThread;
+use crate::builder::{PanicHook, RuntimeBuilder};
+use crate::handle::{self, LoopSender};
+use crate::logger::{self, Level, Logger, Record};
+use crate::nodethread::{NodeThread, WorkerConfig};
 use crate::pollevent::PollEvent;
-use crate::task::{Task, ThreadPoolTaskKind};
+use crate::scheduler::{Lane, OverflowPolicy, SharedQueue, ThreadPoolMode};
+use crate::task::{Task, TaskError, ThreadPoolTaskKind};
+use crate::timer::{TimerCallback, TimerId, Timers};
 use minimio;
 
-pub(crate) static mut RUNTIME: *mut Runtime = std::ptr::null_mut();
+/// Hands out the ids of runtimes, starting at 1.
+static NEXT_RUNTIME_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How often in a row the `on_thread_start` hook of a worker may panic before
+/// the worker isn't replaced anymore.
+const MAX_START_FAILURES: u32 = 3;
+
+/// The result of a task on its way from a worker thread to its callback. The
+/// callback knows the concrete type and downcasts it.
+pub(crate) type Payload = Box<dyn Any + Send>;
+
+/// Work submitted to the thread pool.
+pub(crate) struct ThreadPoolEvent {
+    pub(crate) task: Box<dyn FnOnce() -> Payload + Send + 'static>,
+    pub(crate) kind: ThreadPoolTaskKind,
+    // Receives the result of `task`
+    pub(crate) callback: Box<dyn FnOnce(Payload) + 'static>,
+    // Set by `WorkHandle::cancel` or when the deadline of the work expires
+    pub(crate) cancelled: Arc<AtomicBool>,
+    // Calls the callback with an error instead of the result
+    pub(crate) fail: Rc<dyn Fn(TaskError)>,
+    // Drops the callback without calling it, after `task` panicked
+    pub(crate) discard: Rc<dyn Fn()>,
+}
+
+/// What happened while `Runtime::run` was running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunReport {
+    /// The number of iterations of the loop.
+    pub ticks: usize,
+    /// The number of callbacks the loop ran, not counting the function passed
+    /// to `run`.
+    pub callbacks: usize,
+    /// Set by user code with `Process::set_exit_code`, like Node's
+    /// `process.exitCode`. 0 by default.
+    pub exit_code: i32,
+    /// Everything which went wrong without unwinding out of `run`, in the
+    /// order it happened.
+    pub errors: Vec<RunError>,
+}
+
+impl RunReport {
+    /// Returns `true` if the exit code is 0 and nothing went wrong.
+    pub fn is_success(&self) -> bool {
+        self.exit_code == 0 && self.errors.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunError {
+    /// A callback or a task panicked, and the panic was passed to the
+    /// `on_uncaught_panic` hook. Holds the panic message.
+    UncaughtPanic(String),
+    /// The shutdown timeout expired. Holds the number of callbacks which were
+    /// never called, not counting callbacks of epoll registrations.
+    ShutdownTimedOut(usize),
+    /// The loop finished while callbacks were still waiting for their events,
+    /// which means the runtime lost track of them. Holds their callback ids.
+    /// Only checked in debug builds.
+    LeakedCallbacks(Vec<usize>),
+    /// The `on_thread_start` hook of a worker panicked every time the worker
+    /// was replaced, so it was given up. Holds the id of the worker.
+    WorkerStartFailed(usize),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::UncaughtPanic(message) => write!(f, "uncaught panic: {}", message),
+            RunError::ShutdownTimedOut(abandoned) => write!(
+                f,
+                "the shutdown timed out, {} callbacks were never called",
+                abandoned
+            ),
+            RunError::LeakedCallbacks(ids) => write!(
+                f,
+                "the loop finished with {} callbacks still pending: {:?}",
+                ids.len(),
+                ids
+            ),
+            RunError::WorkerStartFailed(id) => write!(
+                f,
+                "worker {} failed to start {} times in a row",
+                id, MAX_START_FAILURES
+            ),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+/// The kinds of handles which keep the loop alive while they are active, like
+/// libuv's handles. Timers, epoll registrations and `LoopSender`s can be
+/// unreferenced, so they don't keep the loop alive on their own anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandleKind {
+    /// Timeouts and intervals which have not fired or been cleared.
+    Timer,
+    /// Thread pool work which is queued or running.
+    Work,
+    /// Epoll registrations waiting for their event.
+    Epoll,
+    /// `LoopSender`s which exist.
+    Sender,
+}
 
 pub struct Runtime {
+    // Unique in the process, scopes the ids of timers to this runtime
+    id: u64,
     // Pending callbacks
-    callback_pending: HashMap<usize, Box<dyn FnOnce(IOResult)>>,
-    // Ready callbacks
-    callback_ready: Vec<(usize, IOResult)>,
+    callback_pending: HashMap<usize, Box<dyn FnOnce(Payload)>>,
+    // Calls the callback of queued or running work with an error, by callback id
+    work_fail: HashMap<usize, Rc<dyn Fn(TaskError)>>,
+    // Drops the callback of work whose task panicked, by callback id
+    work_discard: HashMap<usize, Rc<dyn Fn()>>,
+    // Ready callbacks, with the payload of the panic if their task panicked
+    callback_ready: VecDeque<(usize, thread::Result<Payload>)>,
     // The unique id for callback function
     callback_token: usize,
-    ///
+    // Registrator of the epoll queue
     pub epoll_registrator: minimio::Registrator,
-    ///
-    epoll_thread: thread::JoinHandle<()>,
-    // Pending events
-    pending_events: usize,
-    ///
-    event_epoll_pending: usize,
-    // event_queue
-    pub(crate) thread_pool_event: Vec<(
-        Box<dyn Fn() -> IOResult + Send + 'static>,
-        ThreadPoolTaskKind,
-        Box<dyn FnOnce(IOResult) + 'static>,
-    )>,
+    // The thread waiting on the epoll queue, joined when the runtime is dropped
+    epoll_thread: Option<thread::JoinHandle<()>>,
+    // Epoll registrations waiting for their event, and whether they keep the
+    // loop alive
+    epoll_handles: HashMap<usize, bool>,
+    // Work waiting for a free thread in the thread pool
+    thread_pool_queue: Arc<SharedQueue>,
+    // The most work `thread_pool_queue` takes before `overflow_policy` applies
+    max_queued_work: Option<usize>,
+    overflow_policy: OverflowPolicy,
     // Event reciever
     event_reciever: Receiver<PollEvent>,
-    // Available threads in thread_pool
+    // Handed to every `LoopSender`
+    event_sender: Sender<PollEvent>,
+    // The number of `LoopSender`s which are alive
+    loop_senders: Arc<AtomicUsize>,
+    // Whether the loop or the workers take work from `thread_pool_queue`
+    thread_pool_mode: ThreadPoolMode,
+    // Available threads in thread_pool, only used by the dispatcher
     thread_available: Vec<usize>,
-    // Thread pool
-    thread_pool: Vec<NodeThread>,
-    ///
-    timers: BTreeMap<Instant, usize>,
-    ///
-    timers_to_remove: Vec<Instant>,
+    // Thread pool, without the workers which failed to start too often
+    thread_pool: Vec<Option<NodeThread>>,
+    // How often in a row the start hook of each worker panicked
+    start_failures: Vec<u32>,
+    // The lane of the task each worker is running or ran last
+    worker_lanes: Vec<Lane>,
+    // Used to replace worker threads which died
+    worker_config: WorkerConfig,
+    // Whether dropping the runtime waits for the workers, which it doesn't
+    // while they may still run abandoned work
+    join_workers: bool,
+    // Receives panics of callbacks and tasks instead of unwinding `run`
+    on_uncaught_panic: Option<PanicHook>,
+    // Timers ordered by their deadline
+    timers: Timers,
+    // Callbacks for the check phase
+    immediates: VecDeque<Box<dyn FnOnce()>>,
+    // Callbacks to run as soon as the current callback returned
+    next_ticks: VecDeque<Box<dyn FnOnce()>>,
+    // Receives the log events of the loop and all its threads
+    logger: Arc<dyn Logger>,
+    // Number of the current iteration of the main loop
+    tick: usize,
+    // Number of callbacks run so far
+    callbacks_run: usize,
+    // Set by user code, returned in the `RunReport`
+    exit_code: i32,
+    // Returned in the `RunReport`
+    errors: Vec<RunError>,
+    // Set once `shutdown` was called
+    shutting_down: bool,
+    // How long the shutdown waits for running work
+    shutdown_timeout: Option<Duration>,
+    // When the shutdown stops waiting for running work
+    drain_deadline: Option<Instant>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Runtime {
+    /// Stops the worker threads and the epoll thread, whether `run` returned,
+    /// unwound, or was never called. Workers are only joined if they can't be
+    /// busy with abandoned work.
+    fn drop(&mut self) {
+        self.thread_pool_queue.close();
+        let join_workers = self.join_workers && !thread::panicking();
+        for (thread_id, thread) in mem::take(&mut self.thread_pool).into_iter().enumerate() {
+            let thread = match thread {
+                Some(thread) => thread,
+                None => continue,
+            };
+            if let Some(sender) = &thread.sender {
+                let _ = sender.send(Task::close());
+            }
+            if join_workers && thread.handle.join().is_err() {
+                self.log(
+                    Record::new(Level::Error, "threadpool", "worker panicked while stopping")
+                        .thread_id(thread_id),
+                );
+            }
+        }
+
+        if let Err(e) = self.epoll_registrator.close_loop() {
+            // The epoll thread would never return
+            self.log(Record::new(
+                Level::Error,
+                "epoll",
+                &format!("closing failed: {}", e),
+            ));
+            return;
+        }
+        let epoll_thread = self.epoll_thread.take();
+        if epoll_thread.is_some_and(|thread| thread.join().is_err()) {
+            self.log(Record::new(Level::Error, "epoll", "epoll thread panicked"));
+        }
+    }
 }
 
 impl Runtime {
+    /// Creates a runtime with the default configuration, see `RuntimeBuilder`.
     pub fn new() -> Self {
-        // main thread
-        let (event_sender, event_reciever) = channel::<PollEvent>();
-        let mut thread_pool = Vec::with_capacity(4);
-
-        for i in 0..4 {
-            let (evt_sender, evt_reciever) = channel::<Task>();
-            let event_sender = event_sender.clone();
-            let thread_id = i;
-
-            let handle = thread::spawn(move || {
-                while let Ok(task) = evt_reciever.recv() {
-                    println!(
-                        "thread {} - received a task of type: {}",
-                        thread_id, task.kind
-                    );
-
-                    if let ThreadPoolTaskKind::Close = task.kind {
-                        println!("thread {} - closed", thread_id);
-                        break;
-                    }
-
-                    let res = (task.task)();
-                    println!(
-                        "thread {} - finished running a task of type: {}.",
-                        thread_id, task.kind
-                    );
+        RuntimeBuilder::new().build()
+    }
 
-                    let event = PollEvent::Threadpool((i, task.callback_id, res));
-                    event_sender.send(event).expect("threadpool");
-                }
-            });
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::new()
+    }
 
-            let node_thread = NodeThread {
-                handle: handle,
-                sender: evt_sender,
-            };
+    pub(crate) fn from_builder(builder: RuntimeBuilder) -> Self {
+        let worker_count = builder.worker_count();
+        let logger = builder.logger;
+        let cpu_workers = worker_count - builder.reserved_io_workers.min(worker_count - 1);
+        let queues = match builder.thread_pool_mode {
+            ThreadPoolMode::Dispatcher => 1,
+            ThreadPoolMode::WorkStealing => worker_count,
+        };
+        let thread_pool_queue = Arc::new(SharedQueue::new(
+            builder.scheduling_policy,
+            cpu_workers,
+            queues,
+        ));
 
-            thread_pool.push(node_thread);
-        }
+        // main thread
+        let (event_sender, event_reciever) = channel::<PollEvent>();
+        let worker_config = WorkerConfig {
+            thread_name: builder.thread_name,
+            thread_stack_size: builder.thread_stack_size,
+            on_thread_start: builder.on_thread_start,
+            on_thread_stop: builder.on_thread_stop,
+            logger: logger.clone(),
+            event_sender: event_sender.clone(),
+            mode: builder.thread_pool_mode,
+            queue: thread_pool_queue.clone(),
+        };
+        let thread_pool = (0..worker_count)
+            .map(|i| Some(NodeThread::spawn(i, &worker_config)))
+            .collect();
 
         // -------- epoll thread --------
         let mut poll = minimio::Poll::new().expect("Error creating epoll queue");
+        poll.enable_stats();
         let registrator = poll.registrator();
-        let epoll_timeout = Arc::new(Mutex::new(None));
-        let epoll_timeout_clone = epoll_timeout.clone();
+        let epoll_logger = logger.clone();
+        let epoll_sender = event_sender.clone();
 
         let epoll_thread = thread::spawn(move || {
             let mut events = minimio::Events::with_capacity(1024);
 
+            // Timers are handled by the main loop, which waits for the next
+            // timer itself, so this thread only needs to wait for I/O.
             loop {
-                let epoll_timeout_handle = epoll_timeout_clone.lock().unwrap();
-                let timeout = *epoll_timeout_handle;
-                drop(epoll_timeout_handle);
-
-                match poll.poll(&mut events, timeout) {
+                match poll.poll(&mut events, None) {
                     Ok(v) if v > 0 => {
                         for i in 0..v {
                             let event = events.get_mut(i).expect("No events in event list.");
-                            println!("epoll event {} is ready", event.id());
+                            logger::log(
+                                &*epoll_logger,
+                                Record::new(Level::Debug, "epoll", "event is ready")
+                                    .callback_id(event.id()),
+                            );
 
-                            let event = PollEvent::Epoll(event.id() as usize);
-                            event_sender.send(event).expect("epoll event");
+                            let event = PollEvent::Epoll(event.id());
+                            epoll_sender.send(event).expect("epoll event");
                         }
                     }
-                    Ok(v) if v == 0 => {
-                        println!("epoll event timeout is ready");
-                        event_sender
+                    Ok(0) => {
+                        logger::log(
+                            &*epoll_logger,
+                            Record::new(Level::Trace, "epoll", "timeout is ready"),
+                        );
+                        epoll_sender
                             .send(PollEvent::Timeout)
                             .expect("epoll timeout");
                     }
                     Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
-                        println!("received event of type: Close");
+                        logger::log(
+                            &*epoll_logger,
+                            Record::new(Level::Debug, "epoll", "received event of type: Close"),
+                        );
                         break;
                     }
                     Err(e) => panic!("{:?}", e),
@@ -132,79 +337,379 @@ impl Runtime {
             }
         });
 
+        let id = NEXT_RUNTIME_ID.fetch_add(1, Ordering::Relaxed);
         Runtime {
-            event_reciever: event_reciever,
+            id,
+            event_reciever,
+            event_sender,
+            loop_senders: Arc::new(AtomicUsize::new(0)),
             epoll_registrator: registrator,
-            epoll_thread: epoll_thread,
-            pending_events: 0,
-            event_epoll_pending: 0,
-            thread_pool_event: vec![],
+            epoll_thread: Some(epoll_thread),
+            epoll_handles: HashMap::new(),
+            thread_pool_queue,
+            max_queued_work: builder.max_queued_work,
+            overflow_policy: builder.overflow_policy,
             callback_pending: HashMap::new(),
-            callback_ready: vec![],
+            work_fail: HashMap::new(),
+            work_discard: HashMap::new(),
+            callback_ready: VecDeque::new(),
             callback_token: 0,
-            thread_available: (0..4).collect(),
-            thread_pool: thread_pool,
-            timers: BTreeMap::new(),
-            timers_to_remove: vec![],
+            thread_pool_mode: builder.thread_pool_mode,
+            thread_available: match builder.thread_pool_mode {
+                ThreadPoolMode::Dispatcher => (0..worker_count).collect(),
+                ThreadPoolMode::WorkStealing => vec![],
+            },
+            thread_pool,
+            start_failures: vec![0; worker_count],
+            worker_lanes: vec![Lane::Io; worker_count],
+            worker_config,
+            join_workers: true,
+            on_uncaught_panic: builder.on_uncaught_panic,
+            timers: Timers::new(id),
+            immediates: VecDeque::new(),
+            next_ticks: VecDeque::new(),
+            logger,
+            tick: 0,
+            callbacks_run: 0,
+            exit_code: 0,
+            errors: vec![],
+            shutting_down: false,
+            shutdown_timeout: builder.shutdown_timeout,
+            drain_deadline: None,
         }
     }
 
-    pub fn run(mut self, async_func: impl Fn()) {
-        let rt_ptr: *mut Runtime = &mut self;
-        unsafe { RUNTIME = rt_ptr };
+    /// Runs `async_func` and then the event loop until there are no pending
+    /// events left. Tasks started from `async_func` or from any callback reach
+    /// this runtime through `RuntimeHandle::current`.
+    ///
+    /// Every iteration of the loop goes through these phases, like libuv:
+    ///
+    /// 1. **timers**: callbacks of expired timeouts and intervals, in the
+    ///    order of their deadlines.
+    /// 2. **pending**: callbacks which became ready outside of the poll phase
+    ///    during the previous iteration.
+    /// 3. **poll**: with `ThreadPoolMode::Dispatcher`, queued work is handed to
+    ///    the thread pool first. Then the loop waits for the thread pool or
+    ///    epoll thread, and runs the callbacks of every event received,
+    ///    including callbacks sent through a `LoopSender`. It doesn't wait if
+    ///    immediates are queued, and never longer than until the next timer
+    ///    expires.
+    /// 4. **check**: callbacks queued with `Immediate::set_immediate`.
+    ///    Immediates queued during this phase run in the next iteration.
+    /// 5. **close**: no handle needs a close callback yet, so this phase is
+    ///    empty.
+    ///
+    /// Callbacks queued with `Process::next_tick` don't belong to a phase. They
+    /// run after `async_func` and after every single callback, before the loop
+    /// continues, including ticks queued by other ticks.
+    ///
+    /// When the loop is done, or the shutdown timeout expired, the worker
+    /// threads and the epoll thread are stopped and joined, like when the
+    /// runtime is dropped. The returned `RunReport` tells what happened.
+    pub fn run(self, async_func: impl Fn()) -> RunReport {
+        let rt = Rc::new(RefCell::new(self));
+        let guard = handle::enter(&rt);
+
+        Runtime::catch(&rt, async_func);
+        Runtime::run_next_ticks(&rt);
 
-        let mut main_ticks = 0;
+        let mut drained = true;
+        while rt.borrow().is_alive() {
+            if rt.borrow().drain_expired() {
+                drained = false;
+                break;
+            }
+            {
+                let mut rt = rt.borrow_mut();
+                // 0. Output the main loop
+                rt.tick += 1;
+                rt.log(Record::new(Level::Trace, "runtime", "main loop tick"));
+            }
+
+            // ===== 1. TIMERS =====
+            Runtime::run_timers(&rt);
+
+            // ===== 2. PENDING =====
+            let pending = rt.borrow().callback_ready.len();
+            Runtime::run_callbacks(&rt, pending);
+
+            // ===== 3. POLL =====
+            Runtime::poll(&rt);
+
+            // ===== 4. CHECK =====
+            Runtime::run_immediates(&rt);
 
-        async_func();
+            // ===== 5. CLOSE =====
+        }
+
+        drop(guard);
+        let mut rt = match Rc::try_unwrap(rt) {
+            Ok(rt) => rt.into_inner(),
+            Err(_) => unreachable!("the runtime is only shared through weak handles"),
+        };
 
-        while self.pending_events > 0 {
-            // 0. Output the main loop
-            main_ticks += 1;
-            println!("===== MAIN LOOP {} =====", main_ticks);
+        let stats = format!("poll stats: {}", rt.poll_stats());
+        rt.log(Record::new(Level::Info, "runtime", &stats));
 
-            if self.thread_pool_event.len() > 0 {
-                self.register_threadpool_event();
+        let mut errors = mem::take(&mut rt.errors);
+        if !drained {
+            rt.log(Record::new(
+                Level::Warn,
+                "runtime",
+                "shutdown timeout expired, abandoning running work",
+            ));
+            let abandoned = rt
+                .callback_pending
+                .keys()
+                .filter(|id| !rt.epoll_handles.contains_key(id))
+                .count();
+            errors.push(RunError::ShutdownTimedOut(abandoned));
+        } else if cfg!(debug_assertions) {
+            // Unreferenced epoll registrations are allowed to wait forever
+            let mut leaked: Vec<usize> = rt
+                .callback_pending
+                .keys()
+                .filter(|id| !rt.epoll_handles.contains_key(id))
+                .copied()
+                .collect();
+            leaked.sort_unstable();
+            for &id in &leaked {
+                rt.log(
+                    Record::new(Level::Error, "runtime", "callback still pending at exit")
+                        .callback_id(id),
+                );
             }
+            if !leaked.is_empty() {
+                errors.push(RunError::LeakedCallbacks(leaked));
+            }
+        }
+        let report = RunReport {
+            ticks: rt.tick,
+            callbacks: rt.callbacks_run,
+            exit_code: rt.exit_code,
+            errors,
+        };
+
+        // Workers which may still be running abandoned work are detached
+        rt.join_workers = drained;
+        report
+    }
+
+    /// Starts a graceful shutdown of the runtime running on this thread, like
+    /// sending it a termination signal. Can be called from any callback, or
+    /// posted through a `LoopSender` from another thread.
+    ///
+    /// Timers and intervals are cleared, and queued thread pool work, as well
+    /// as work submitted later, is cancelled: its callback is called with
+    /// `TaskError::Cancelled`. `LoopSender`s don't keep the loop alive anymore.
+    /// The loop keeps running until the work which is already running has
+    /// called back, or until `RuntimeBuilder::shutdown_timeout` expired.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of `Runtime::run`.
+    pub fn shutdown() {
+        handle::with_current(|rt| rt.begin_shutdown());
+    }
 
-            self.process_expired_timers();
+    pub(crate) fn begin_shutdown(&mut self) {
+        if self.shutting_down {
+            return;
+        }
+        self.shutting_down = true;
+        self.drain_deadline = self
+            .shutdown_timeout
+            .map(|timeout| Instant::now() + timeout);
+        self.log(Record::new(Level::Info, "runtime", "shutting down"));
 
-            self.run_callbacks();
+        self.timers.clear();
+        self.fail_queued(TaskError::Cancelled);
+    }
 
-            if self.pending_events == 0 {
-                break;
+    /// Returns `true` once the shutdown stopped waiting for running work.
+    fn drain_expired(&self) -> bool {
+        self.drain_deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns `true` while there is work the loop has to wait for, including
+    /// work queued by a callback which has not been handed to a thread yet.
+    fn is_alive(&self) -> bool {
+        self.is_waiting() || !self.immediates.is_empty() || !self.callback_ready.is_empty()
+    }
+
+    /// Returns `true` while a referenced handle may still deliver an event in
+    /// the poll phase.
+    fn is_waiting(&self) -> bool {
+        self.active_handles(HandleKind::Timer) > 0
+            || self.active_handles(HandleKind::Work) > 0
+            || self.active_handles(HandleKind::Epoll) > 0
+            || (!self.shutting_down && self.active_handles(HandleKind::Sender) > 0)
+    }
+
+    /// The number of active handles of `kind` which keep the loop alive.
+    /// Unreferenced handles are not counted.
+    pub fn active_handles(&self, kind: HandleKind) -> usize {
+        match kind {
+            HandleKind::Timer => self.timers.referenced(),
+            // Queued and running work always keeps the loop alive
+            HandleKind::Work => self.work_fail.len(),
+            HandleKind::Epoll => self.epoll_handles.values().filter(|r| **r).count(),
+            HandleKind::Sender => self.loop_senders.load(Ordering::SeqCst),
+        }
+    }
+
+    /// The poll phase. Waits for events from the thread pool and the epoll
+    /// thread and runs their callbacks.
+    fn poll(rt: &RefCell<Runtime>) {
+        let mut rt_mut = rt.borrow_mut();
+        rt_mut.register_threadpool_event();
+
+        // Wait for the next event, but no longer than until the next timer
+        // expires, and not at all if there is nothing to wait for or more
+        // callbacks are waiting in the check phase.
+        let event = if !rt_mut.immediates.is_empty() || !rt_mut.is_waiting() {
+            rt_mut.event_reciever.try_recv().ok()
+        } else {
+            match rt_mut.get_next_timer() {
+                Some(timeout) => rt_mut.event_reciever.recv_timeout(timeout).ok(),
+                None => rt_mut.event_reciever.recv().ok(),
             }
+        };
 
-            // NOT PART OF LOOP, JUST FOR US TO SEE WHAT TICK IS EXCECUTING
-            // ===== 4. POLL =====
-            // First we need to check if we have any outstanding events at all
-            // and if not we're finished. If not we will wait forever.
-            if let Ok(event) = self.event_reciever.recv() {
-                match event {
-                    PollEvent::Threadpool((thread_id, callback_id, data)) => {
-                        self.process_threadpool_event(thread_id, callback_id, data);
-                    }
-                    PollEvent::Epoll(event_id) => {
-                        self.process_epoll_event(event_id);
-                    }
-                    PollEvent::Timeout => (),
+        let mut next_event = event;
+        let mut received = 0;
+        while let Some(event) = next_event {
+            match event {
+                PollEvent::Threadpool((thread_id, callback_id, data)) => {
+                    rt_mut.process_threadpool_event(thread_id, callback_id, data);
+                    received += 1;
+                }
+                PollEvent::Epoll(event_id) => {
+                    rt_mut.process_epoll_event(event_id);
+                    received += 1;
+                }
+                PollEvent::Posted(f) => {
+                    let callback_id = rt_mut.generate_cb_identity();
+                    rt_mut.add_callback(callback_id, move |_| f());
+                    rt_mut
+                        .callback_ready
+                        .push_back((callback_id, Ok(Box::new(()))));
+                    received += 1;
+                }
+                PollEvent::Timeout | PollEvent::Unreferenced => (),
+                PollEvent::WorkerDied((thread_id, started, running, channel)) => {
+                    rt_mut.respawn_worker(thread_id, started, running, channel);
+                    received += running.is_some() as usize;
                 }
             }
-            self.run_callbacks();
+            next_event = rt_mut.event_reciever.try_recv().ok();
         }
+        drop(rt_mut);
+
+        Runtime::run_callbacks(rt, received);
+    }
 
-        // Close the threadpool
-        for thread in self.thread_pool.into_iter() {
-            thread
-                .sender
-                .send(Task::close())
-                .expect("threadpool cleanup");
-            thread.handle.join().unwrap();
+    /// The check phase. Runs the immediates which were queued before the
+    /// phase started.
+    fn run_immediates(rt: &RefCell<Runtime>) {
+        let count = rt.borrow().immediates.len();
+        for _ in 0..count {
+            let cb = match rt.borrow_mut().immediates.pop_front() {
+                Some(cb) => cb,
+                None => break,
+            };
+            Runtime::call(rt, cb);
+            Runtime::run_next_ticks(rt);
         }
     }
 
+    /// Runs the next tick queue until it is empty.
+    fn run_next_ticks(rt: &RefCell<Runtime>) {
+        loop {
+            let cb = match rt.borrow_mut().next_ticks.pop_front() {
+                Some(cb) => cb,
+                None => break,
+            };
+            Runtime::call(rt, cb);
+        }
+    }
+
+    /// Runs a callback. If it panics, the panic is passed to the
+    /// `on_uncaught_panic` hook, or resumed if there is none.
+    fn call(rt: &RefCell<Runtime>, f: impl FnOnce()) {
+        rt.borrow_mut().callbacks_run += 1;
+        Runtime::catch(rt, f);
+    }
+
+    /// Runs `f` like `call`, without counting it as a callback.
+    fn catch(rt: &RefCell<Runtime>, f: impl FnOnce()) {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            Runtime::uncaught_panic(rt, payload);
+        }
+    }
+
+    fn uncaught_panic(rt: &RefCell<Runtime>, payload: Payload) {
+        let hook = {
+            let mut rt = rt.borrow_mut();
+            rt.log(Record::new(Level::Error, "runtime", "uncaught panic"));
+            if rt.on_uncaught_panic.is_some() {
+                let message = panic_message(&*payload);
+                rt.errors.push(RunError::UncaughtPanic(message));
+            }
+            rt.on_uncaught_panic.clone()
+        };
+        match hook {
+            Some(hook) => hook(payload),
+            None => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Sets the exit code returned in the `RunReport`.
+    pub fn set_exit_code(&mut self, code: i32) {
+        self.exit_code = code;
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    /// A number which identifies this runtime among all runtimes of the
+    /// process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns a sender which posts callbacks into the loop from any thread.
+    /// The loop doesn't finish while a referenced sender exists.
+    pub fn loop_sender(&self) -> LoopSender {
+        LoopSender::new(self.event_sender.clone(), self.loop_senders.clone())
+    }
+
+    /// Queues `cb` to run in the check phase of the loop.
+    pub fn set_immediate(&mut self, cb: impl FnOnce() + 'static) {
+        self.immediates.push_back(Box::new(cb));
+    }
+
+    /// Queues `cb` to run as soon as the current callback returned.
+    pub fn next_tick(&mut self, cb: impl FnOnce() + 'static) {
+        self.next_ticks.push_back(Box::new(cb));
+    }
+
+    /// Logs `record`, tagged with the current tick of the main loop.
+    fn log(&self, record: Record) {
+        logger::log(&*self.logger, record.tick(self.tick));
+    }
+
+    /// Returns a snapshot of what the epoll thread has been doing so far.
+    pub fn poll_stats(&self) -> minimio::PollStats {
+        self.epoll_registrator.stats()
+    }
+
     fn add_callback<U>(&mut self, ident: usize, cb: U)
     where
-        U: FnOnce(IOResult) + 'static,
+        U: FnOnce(Payload) + 'static,
     {
         self.callback_pending.insert(ident, Box::new(cb));
     }
@@ -223,90 +728,386 @@ impl Runtime {
         }
     }
 
-    fn process_expired_timers(&mut self) {
-        let timers_to_remove = &mut self.timers_to_remove;
+    /// Runs the callbacks of all expired timers, one at a time, so a callback
+    /// can still clear a timer which expired in the same tick.
+    fn run_timers(rt: &RefCell<Runtime>) {
+        let now = Instant::now();
+        loop {
+            let cb = {
+                let mut rt = rt.borrow_mut();
+                match rt.timers.pop_expired(now) {
+                    Some((cb, _)) => cb,
+                    None => break,
+                }
+            };
+            Runtime::call(rt, || cb());
+            Runtime::run_next_ticks(rt);
+        }
+    }
+
+    /// Returns the time left until the next timer expires, or until the
+    /// shutdown stops waiting for running work.
+    fn get_next_timer(&self) -> Option<Duration> {
+        let deadline = match (self.timers.next_deadline(), self.drain_deadline) {
+            (Some(timer), Some(drain)) => Some(timer.min(drain)),
+            (timer, drain) => timer.or(drain),
+        };
+        deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
 
-        self.timers
-            .range(..=Instant::now())
-            .for_each(|(k, _)| timers_to_remove.push(*k));
+    /// Hands queued work to the free workers, unless the workers take it
+    /// themselves. CPU bound work only gets a worker while fewer than
+    /// `cpu_workers` are busy with it.
+    fn register_threadpool_event(&mut self) {
+        while !self.thread_available.is_empty() {
+            let task = match self.thread_pool_queue.try_take(0) {
+                Some(task) => task,
+                None => break,
+            };
+
+            let thread_id = self.thread_available.pop().unwrap();
+            self.send_to_worker(thread_id, task);
+        }
+    }
 
-        while let Some(key) = self.timers_to_remove.pop() {
-            let callback_id = self.timers.remove(&key).unwrap();
-            self.callback_ready.push((callback_id, IOResult::Undefined));
+    /// Hands `task` to the idle worker `thread_id`. If the worker died in the
+    /// meantime, the task goes back to the queue, and the worker is available
+    /// again once its `WorkerDied` event was handled.
+    fn send_to_worker(&mut self, thread_id: usize, task: Task) {
+        self.worker_lanes[thread_id] = task.kind.lane();
+        let sender = self.thread_pool[thread_id]
+            .as_ref()
+            .and_then(|worker| worker.sender.as_ref())
+            .expect("dispatched workers have a channel");
+        if let Err(SendError(task)) = sender.send(task) {
+            self.thread_pool_queue.put_back(task);
         }
     }
 
-    fn get_next_timer(&self) -> Option<i32> {
-        self.timers.iter().nth(0).map(|(&instant, _)| {
-            let mut time_to_next_timeout = instant - Instant::now();
-            if time_to_next_timeout < Duration::new(0, 0) {
-                time_to_next_timeout = Duration::new(0, 0);
+    /// Queues work for the thread pool. If the queue is full, the overflow
+    /// policy decides what happens. Returns `true` if the work was turned away
+    /// by `OverflowPolicy::Busy`.
+    ///
+    /// With `ThreadPoolMode::WorkStealing` an idle worker starts the work right away,
+    /// even while the callback which submitted it is still running.
+    pub(crate) fn queue_work(&mut self, work: ThreadPoolEvent) -> bool {
+        if self.shutting_down {
+            self.fail_work(&work.cancelled, work.fail, TaskError::Cancelled);
+            return false;
+        }
+        if self.thread_pool.iter().all(Option::is_none) {
+            self.fail_work(&work.cancelled, work.fail, TaskError::NoWorkers);
+            return false;
+        }
+
+        let full = self
+            .max_queued_work
+            .is_some_and(|max| self.queue_depth() >= max);
+        if full {
+            self.log(
+                Record::new(Level::Warn, "threadpool", "queue is full").task_kind(work.kind.name()),
+            );
+            match self.overflow_policy {
+                OverflowPolicy::Reject => {
+                    self.fail_work(&work.cancelled, work.fail, TaskError::QueueFull);
+                    return false;
+                }
+                OverflowPolicy::DropOldest => {
+                    let oldest = self.thread_pool_queue.pop_oldest();
+                    if let Some(oldest) = oldest {
+                        let fail = self.forget_work(&oldest);
+                        self.fail_work(&oldest.cancelled, fail, TaskError::QueueFull);
+                    }
+                }
+                OverflowPolicy::Busy => {
+                    // Nobody may call the callback anymore
+                    work.cancelled.store(true, Ordering::SeqCst);
+                    return true;
+                }
             }
-            time_to_next_timeout.as_millis() as i32
-        })
+        }
+
+        // The callback has to be known before a worker can finish the task
+        let callback_id = self.generate_cb_identity();
+        self.add_callback(callback_id, work.callback);
+        self.work_fail.insert(callback_id, work.fail);
+        self.work_discard.insert(callback_id, work.discard);
+        self.thread_pool_queue.push(Task {
+            task: work.task,
+            callback_id,
+            kind: work.kind,
+            cancelled: work.cancelled,
+        });
+        false
     }
 
-    fn register_threadpool_event(&mut self) {
+    /// Calls the callback of work which won't run with `e` in the check phase.
+    fn fail_work(&mut self, cancelled: &AtomicBool, fail: Rc<dyn Fn(TaskError)>, e: TaskError) {
+        cancelled.store(true, Ordering::SeqCst);
+        self.set_immediate(move || fail(e));
+    }
+
+    /// Fails all queued work with `e`, oldest first.
+    fn fail_queued(&mut self, e: TaskError) {
         loop {
-            if self.thread_pool_event.len() > 0 && self.thread_available.len() > 0 {
-                let (task, kind, cb) = self.thread_pool_event.pop().unwrap();
-
-                let callback_id = self.generate_cb_identity();
-                self.add_callback(callback_id, cb);
-
-                let thread_id = self.thread_available.pop().unwrap();
-                let event = Task {
-                    task: task,
-                    callback_id: callback_id,
-                    kind: kind,
-                };
-                self.thread_pool[thread_id]
-                    .sender
-                    .send(event)
-                    .expect("register work");
-                self.pending_events += 1;
-            } else {
-                break;
+            let task = self.thread_pool_queue.pop_oldest();
+            match task {
+                Some(task) => {
+                    let fail = self.forget_work(&task);
+                    self.fail_work(&task.cancelled, fail, e);
+                }
+                None => break,
             }
         }
     }
 
-    pub fn register_epoll_event(&mut self, token: usize, cb: impl FnOnce(IOResult) + 'static) {
-        self.add_callback(token, cb);
-        println!("Event with id: {} registered.", token);
-        self.pending_events += 1;
-        self.event_epoll_pending += 1;
+    /// Drops the callback of queued work which was taken out of the queue
+    /// again. Returns the function which fails the work instead.
+    fn forget_work(&mut self, task: &Task) -> Rc<dyn Fn(TaskError)> {
+        self.callback_pending.remove(&task.callback_id);
+        self.work_discard.remove(&task.callback_id);
+        self.work_fail
+            .remove(&task.callback_id)
+            .expect("queued work can fail")
     }
 
-    fn process_threadpool_event(&mut self, thread_id: usize, callback_id: usize, data: IOResult) {
-        self.callback_ready.push((callback_id, data));
-        self.thread_available.push(thread_id);
+    /// The number of tasks waiting for a free thread in the thread pool.
+    pub fn queue_depth(&self) -> usize {
+        self.thread_pool_queue.len()
+    }
+
+    /// Removes the work with the cancellation flag `cancelled` from the queue.
+    /// Returns `false` if the work isn't queued anymore.
+    pub(crate) fn cancel_queued(&mut self, cancelled: &Arc<AtomicBool>) -> bool {
+        let task = self
+            .thread_pool_queue
+            .remove(|task| Arc::ptr_eq(&task.cancelled, cancelled));
+        match task {
+            Some(task) => {
+                // The handle calls the callback itself
+                self.forget_work(&task);
+                self.log(
+                    Record::new(Level::Debug, "threadpool", "queued work cancelled")
+                        .callback_id(task.callback_id)
+                        .task_kind(task.kind.name()),
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the worker `thread_id` which died. A task it had received but
+    /// not started runs on the new worker, a task it was running is reported
+    /// like a task which panicked.
+    ///
+    /// A worker whose `on_thread_start` hook panicked `MAX_START_FAILURES`
+    /// times in a row isn't replaced. Once no worker is left, queued work
+    /// fails with `TaskError::NoWorkers`.
+    fn respawn_worker(
+        &mut self,
+        thread_id: usize,
+        started: bool,
+        running: Option<usize>,
+        channel: Option<Receiver<Task>>,
+    ) {
+        let queued = channel.and_then(|receiver| receiver.try_recv().ok());
+        let failures = &mut self.start_failures[thread_id];
+        *failures = if started { 0 } else { *failures + 1 };
+        let give_up = *failures >= MAX_START_FAILURES;
+
+        let worker = if give_up {
+            self.log(
+                Record::new(
+                    Level::Error,
+                    "threadpool",
+                    "worker failed to start, giving up",
+                )
+                .thread_id(thread_id),
+            );
+            None
+        } else {
+            self.log(
+                Record::new(Level::Error, "threadpool", "worker died, respawning")
+                    .thread_id(thread_id),
+            );
+            Some(NodeThread::spawn(thread_id, &self.worker_config))
+        };
+        let dead = mem::replace(&mut self.thread_pool[thread_id], worker);
+        if let Some(dead) = dead {
+            let _ = dead.handle.join();
+        }
+
+        if let Some(callback_id) = running {
+            let payload: Payload = Box::new("the worker thread died while running the task");
+            self.callback_ready.push_back((callback_id, Err(payload)));
+        }
+
+        if give_up {
+            self.errors.push(RunError::WorkerStartFailed(thread_id));
+            self.thread_available.retain(|&id| id != thread_id);
+            if let Some(task) = queued {
+                self.thread_pool_queue.put_back(task);
+            }
+            if self.thread_pool.iter().all(Option::is_none) {
+                self.fail_queued(TaskError::NoWorkers);
+            }
+            return;
+        }
+
+        match queued {
+            Some(task) => self.send_to_worker(thread_id, task),
+            None if running.is_some() => self.worker_finished(thread_id),
+            // Work sent to the dead worker was put back into the queue
+            None if self.thread_pool_mode == ThreadPoolMode::Dispatcher
+                && !self.thread_available.contains(&thread_id) =>
+            {
+                self.thread_available.push(thread_id)
+            }
+            None => (),
+        }
+    }
+
+    /// Makes the worker available again after it finished a task. Work
+    /// stealing workers take care of that themselves.
+    fn worker_finished(&mut self, thread_id: usize) {
+        if self.thread_pool_mode == ThreadPoolMode::Dispatcher {
+            self.thread_pool_queue
+                .finished(self.worker_lanes[thread_id]);
+            self.thread_available.push(thread_id);
+        }
+    }
+
+    /// Calls `cb` when the epoll thread reports an event for `token`. The
+    /// registration keeps the loop alive until then, unless it is unreferenced
+    /// with `set_epoll_ref`.
+    pub fn register_epoll_event(&mut self, token: usize, cb: impl FnOnce() + 'static) {
+        self.add_callback(token, move |_| cb());
+        self.epoll_handles.insert(token, true);
+        self.log(Record::new(Level::Debug, "epoll", "event registered").callback_id(token));
+    }
+
+    /// Sets whether the epoll registration `token` keeps the loop alive.
+    /// Returns `false` if its event already arrived.
+    pub fn set_epoll_ref(&mut self, token: usize, referenced: bool) -> bool {
+        match self.epoll_handles.get_mut(&token) {
+            Some(r) => {
+                *r = referenced;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the epoll registration `token` is waiting for its
+    /// event and keeps the loop alive.
+    pub fn epoll_has_ref(&self, token: usize) -> bool {
+        self.epoll_handles.get(&token).copied().unwrap_or(false)
+    }
+
+    fn process_threadpool_event(
+        &mut self,
+        thread_id: usize,
+        callback_id: usize,
+        data: thread::Result<Payload>,
+    ) {
+        self.callback_ready.push_back((callback_id, data));
+        self.worker_finished(thread_id);
     }
 
     fn process_epoll_event(&mut self, event_id: usize) {
-        self.callback_ready.push((event_id, IOResult::Undefined));
-        self.pending_events -= 1;
+        if self.epoll_handles.remove(&event_id).is_some() {
+            self.callback_ready.push_back((event_id, Ok(Box::new(()))));
+        }
+    }
+
+    /// Runs the first `count` ready callbacks. The runtime is not borrowed
+    /// while a callback runs, so callbacks can start new tasks.
+    fn run_callbacks(rt: &RefCell<Runtime>, count: usize) {
+        for _ in 0..count {
+            let (cb, discard, data) = {
+                let mut rt = rt.borrow_mut();
+                match rt.callback_ready.pop_front() {
+                    Some((callback_id, data)) => {
+                        rt.work_fail.remove(&callback_id);
+                        let discard = rt.work_discard.remove(&callback_id);
+                        let cb = rt.callback_pending.remove(&callback_id).unwrap();
+                        (cb, discard, data)
+                    }
+                    None => break,
+                }
+            };
+            match data {
+                Ok(data) => Runtime::call(rt, move || cb(data)),
+                // The callback never gets a result from a task which panicked,
+                // and neither a cancellation nor the deadline may call it later
+                Err(payload) => {
+                    drop(cb);
+                    if let Some(discard) = discard {
+                        discard();
+                    }
+                    Runtime::uncaught_panic(rt, payload);
+                }
+            }
+            Runtime::run_next_ticks(rt);
+        }
+    }
+
+    pub fn set_timeout(&mut self, ms: u64, cb: impl Fn() + 'static) -> TimerId {
+        self.add_timer(Duration::from_millis(ms), None, Rc::new(cb))
+    }
+
+    pub fn set_interval(&mut self, ms: u64, cb: impl Fn() + 'static) -> TimerId {
+        let interval = Duration::from_millis(ms);
+        self.add_timer(interval, Some(interval), Rc::new(cb))
     }
 
-    fn run_callbacks(&mut self) {
-        while let Some((callback_id, data)) = self.callback_ready.pop() {
-            let cb = self.callback_pending.remove(&callback_id).unwrap();
-            cb(data);
-            self.pending_events -= 1;
+    /// Clears a timeout or an interval. Returns `false` if the timer already
+    /// fired or was cleared before.
+    pub fn clear_timer(&mut self, id: TimerId) -> bool {
+        let cleared = self.timers.remove(id);
+        if cleared {
+            self.log(Record::new(Level::Debug, "runtime", "timer cleared").timer_id(id.as_u64()));
         }
+        cleared
     }
 
-    pub fn set_timeout(&mut self, ms: u64, cb: impl Fn(IOResult) + 'static) {
-        let now = Instant::now();
+    /// Sets whether a timer keeps the loop alive. Returns `false` if the timer
+    /// already fired or was cleared.
+    pub fn set_timer_ref(&mut self, id: TimerId, referenced: bool) -> bool {
+        self.timers.set_ref(id, referenced)
+    }
 
-        let cb_id = self.generate_cb_identity();
-        self.add_callback(cb_id, cb);
+    /// Returns `true` if the timer is waiting and keeps the loop alive.
+    pub fn timer_has_ref(&self, id: TimerId) -> bool {
+        self.timers.has_ref(id)
+    }
 
-        let timeout = now + Duration::from_millis(ms);
-        self.timers.insert(timeout, cb_id);
+    fn add_timer(
+        &mut self,
+        delay: Duration,
+        interval: Option<Duration>,
+        cb: TimerCallback,
+    ) -> TimerId {
+        let id = self.timers.insert(Instant::now() + delay, interval, cb);
+        // A timer set while shutting down never fires
+        if self.shutting_down {
+            self.timers.remove(id);
+            return id;
+        }
+        self.log(Record::new(Level::Debug, "runtime", "timer registered").timer_id(id.as_u64()));
+        id
+    }
+}
 
-        self.pending_events += 1;
-        println!("Registered timer event id: {}", cb_id);
+/// The message of a panic, if it has one.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
     }
 }
 
@@ -341,3 +1142,53 @@ fn test_generate_cb_identity() {
         assert_eq!(ident, 3);
     */
 }
+
+#[test]
+fn test_epoll_events_are_counted_once() {
+    let called = Rc::new(RefCell::new(0));
+
+    let called_clone = called.clone();
+    let report = Runtime::new().run(move || {
+        let called = called_clone.clone();
+        handle::with_current(|rt| {
+            let token = rt.generate_cb_identity();
+            rt.register_epoll_event(token, move || *called.borrow_mut() += 1);
+            assert_eq!(rt.active_handles(HandleKind::Epoll), 1);
+            // What the epoll thread sends when the stream is readable
+            rt.event_sender.send(PollEvent::Epoll(token)).unwrap();
+
+            // Never gets an event, but doesn't keep the loop alive either
+            let idle = rt.generate_cb_identity();
+            rt.register_epoll_event(idle, || unreachable!());
+            assert!(rt.set_epoll_ref(idle, false));
+            assert!(!rt.epoll_has_ref(idle));
+            assert_eq!(rt.active_handles(HandleKind::Epoll), 1);
+        });
+    });
+
+    assert_eq!(*called.borrow(), 1);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+}
+
+#[test]
+fn test_shutdown_timeout_doesnt_count_epoll_registrations() {
+    let report = Runtime::builder()
+        .worker_threads(1)
+        .shutdown_timeout(Duration::from_millis(20))
+        .build()
+        .run(|| {
+            crate::task::spawn_blocking(
+                "slow",
+                || thread::sleep(Duration::from_millis(300)),
+                |_| (),
+            );
+            handle::with_current(|rt| {
+                let idle = rt.generate_cb_identity();
+                rt.register_epoll_event(idle, || unreachable!());
+                rt.set_epoll_ref(idle, false);
+            });
+            crate::task::Timeout::set_timeout(10, Runtime::shutdown);
+        });
+
+    assert_eq!(report.errors, vec![RunError::ShutdownTimedOut(1)]);
+}
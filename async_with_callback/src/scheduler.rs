@@ -0,0 +1,637 @@
+use std::{
+    cmp,
+    collections::{BinaryHeap, VecDeque},
+    mem,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+};
+
+use crate::task::Task;
+
+/// Decides in which order queued work is handed to the thread pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedulingPolicy {
+    /// Work runs in the order it was submitted.
+    #[default]
+    Fifo,
+    /// The most recently submitted work runs first.
+    Lifo,
+    /// Work with a higher `ThreadPoolTaskKind::priority` runs first. Work with
+    /// the same priority runs in the order it was submitted.
+    Priority,
+}
+
+/// Decides what happens to new thread pool work when the queue already holds
+/// `RuntimeBuilder::max_queued_work` tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// The new work is not queued, its callback is called with
+    /// `TaskError::QueueFull`.
+    #[default]
+    Reject,
+    /// The work which was submitted first is removed from the queue and its
+    /// callback is called with `TaskError::QueueFull`, the new work is queued.
+    DropOldest,
+    /// The new work is not queued and its callback is never called.
+    /// `WorkHandle::is_busy` tells the caller to try again later.
+    Busy,
+}
+
+/// Decides how queued work gets to the worker threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThreadPoolMode {
+    /// The loop hands queued work to idle workers in its poll phase, over a
+    /// channel per worker. Work submitted by a callback waits until the loop
+    /// gets there.
+    #[default]
+    Dispatcher,
+    /// Every worker has a queue of its own, which submitted work is spread
+    /// over in turn. Workers take work from their own queue as soon as it is
+    /// submitted, without waiting for the loop, and steal from the queues of
+    /// the others once theirs is empty.
+    WorkStealing,
+}
+
+/// Thread pool work is split into two lanes, so CPU bound work can be kept
+/// from occupying every worker, see `RuntimeBuilder::reserved_io_workers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// File system work, which mostly waits for the disk.
+    Io,
+    /// CPU bound work, including everything submitted with `spawn_blocking`.
+    Cpu,
+}
+
+/// The queue of work waiting for a free thread in the thread pool.
+pub(crate) struct WorkQueue {
+    policy: SchedulingPolicy,
+    io: Inner,
+    cpu: Inner,
+    // The highest submission number, keeps the priority queue stable
+    seq: u64,
+}
+
+enum Inner {
+    Deque(VecDeque<PriorityEntry>),
+    Heap(BinaryHeap<PriorityEntry>),
+}
+
+struct PriorityEntry {
+    priority: u8,
+    seq: u64,
+    task: Task,
+}
+
+impl PartialEq for PriorityEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for PriorityEntry {}
+
+impl PartialOrd for PriorityEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PriorityEntry {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        // `BinaryHeap` is a max heap, so the lower sequence number needs to
+        // compare as the greater one.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl Inner {
+    fn new(policy: SchedulingPolicy) -> Self {
+        match policy {
+            SchedulingPolicy::Priority => Inner::Heap(BinaryHeap::new()),
+            _ => Inner::Deque(VecDeque::new()),
+        }
+    }
+
+    fn push(&mut self, entry: PriorityEntry) {
+        match self {
+            Inner::Deque(queue) => queue.push_back(entry),
+            Inner::Heap(heap) => heap.push(entry),
+        }
+    }
+
+    /// Adds an entry where `pop` takes it first.
+    fn push_next(&mut self, entry: PriorityEntry, policy: SchedulingPolicy) {
+        match self {
+            Inner::Deque(queue) if policy == SchedulingPolicy::Lifo => queue.push_back(entry),
+            Inner::Deque(queue) => queue.push_front(entry),
+            Inner::Heap(heap) => heap.push(entry),
+        }
+    }
+
+    /// The entry `pop` would return next.
+    fn peek(&self, policy: SchedulingPolicy) -> Option<&PriorityEntry> {
+        match self {
+            Inner::Deque(queue) if policy == SchedulingPolicy::Lifo => queue.back(),
+            Inner::Deque(queue) => queue.front(),
+            Inner::Heap(heap) => heap.peek(),
+        }
+    }
+
+    fn pop(&mut self, policy: SchedulingPolicy) -> Option<PriorityEntry> {
+        match self {
+            Inner::Deque(queue) if policy == SchedulingPolicy::Lifo => queue.pop_back(),
+            Inner::Deque(queue) => queue.pop_front(),
+            Inner::Heap(heap) => heap.pop(),
+        }
+    }
+
+    /// Removes the first entry `f` returns `true` for.
+    fn remove(&mut self, f: impl Fn(&PriorityEntry) -> bool) -> Option<PriorityEntry> {
+        match self {
+            Inner::Deque(queue) => {
+                let i = queue.iter().position(f)?;
+                queue.remove(i)
+            }
+            Inner::Heap(heap) => {
+                let mut entries = mem::take(heap).into_vec();
+                let removed = entries.iter().position(f).map(|i| entries.swap_remove(i));
+                *heap = entries.into();
+                removed
+            }
+        }
+    }
+
+    fn oldest(&self) -> Option<u64> {
+        match self {
+            Inner::Deque(queue) => queue.front().map(|entry| entry.seq),
+            Inner::Heap(heap) => heap.iter().map(|entry| entry.seq).min(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Inner::Deque(queue) => queue.len(),
+            Inner::Heap(heap) => heap.len(),
+        }
+    }
+}
+
+impl WorkQueue {
+    pub(crate) fn new(policy: SchedulingPolicy) -> Self {
+        WorkQueue {
+            policy,
+            io: Inner::new(policy),
+            cpu: Inner::new(policy),
+            seq: 0,
+        }
+    }
+
+    /// Queues `task` with the submission number `seq`, which also orders it
+    /// against the work of other queues.
+    pub(crate) fn push(&mut self, task: Task, seq: u64) {
+        self.seq = self.seq.max(seq);
+        let entry = PriorityEntry {
+            priority: task.kind.priority(),
+            seq,
+            task,
+        };
+        match entry.task.kind.lane() {
+            Lane::Io => self.io.push(entry),
+            Lane::Cpu => self.cpu.push(entry),
+        }
+    }
+
+    /// Puts work which was taken with `pop` back, ahead of the work of the
+    /// same priority which is still queued.
+    pub(crate) fn push_next(&mut self, task: Task) {
+        let seq = match self.policy {
+            SchedulingPolicy::Lifo => {
+                self.seq += 1;
+                self.seq
+            }
+            _ => 0,
+        };
+        let entry = PriorityEntry {
+            priority: task.kind.priority(),
+            seq,
+            task,
+        };
+        match entry.task.kind.lane() {
+            Lane::Io => self.io.push_next(entry, self.policy),
+            Lane::Cpu => self.cpu.push_next(entry, self.policy),
+        }
+    }
+
+    /// Takes the next work in the order of the scheduling policy. Work of the
+    /// CPU lane is skipped if `cpu` is `false`.
+    pub(crate) fn pop(&mut self, cpu: bool) -> Option<Task> {
+        let policy = self.policy;
+        let io_first = match (self.io.peek(policy), self.cpu.peek(policy)) {
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (Some(io), Some(cpu)) => match policy {
+                SchedulingPolicy::Fifo => io.seq < cpu.seq,
+                SchedulingPolicy::Lifo => io.seq > cpu.seq,
+                SchedulingPolicy::Priority => io > cpu,
+            },
+            (None, None) => return None,
+        };
+
+        let lane = if io_first || !cpu {
+            &mut self.io
+        } else {
+            &mut self.cpu
+        };
+        lane.pop(policy).map(|entry| entry.task)
+    }
+
+    /// The submission number of the work which was submitted first.
+    pub(crate) fn oldest(&self) -> Option<u64> {
+        match (self.io.oldest(), self.cpu.oldest()) {
+            (Some(io), Some(cpu)) => Some(io.min(cpu)),
+            (io, cpu) => io.or(cpu),
+        }
+    }
+
+    /// Removes the work which was submitted first, whatever the policy.
+    pub(crate) fn pop_oldest(&mut self) -> Option<Task> {
+        let lane = match (self.io.oldest(), self.cpu.oldest()) {
+            (Some(io), Some(cpu)) if cpu < io => &mut self.cpu,
+            (None, Some(_)) => &mut self.cpu,
+            _ => &mut self.io,
+        };
+        let oldest = lane.oldest()?;
+        lane.remove(|entry| entry.seq == oldest)
+            .map(|entry| entry.task)
+    }
+
+    /// Removes the first queued work `f` returns `true` for.
+    pub(crate) fn remove(&mut self, f: impl Fn(&Task) -> bool) -> Option<Task> {
+        self.io
+            .remove(|entry| f(&entry.task))
+            .or_else(|| self.cpu.remove(|entry| f(&entry.task)))
+            .map(|entry| entry.task)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.io.len() + self.cpu.len()
+    }
+}
+
+/// The work queues of the thread pool, shared between the loop, which submits
+/// work, and the workers. With `ThreadPoolMode::Dispatcher` there is a single
+/// queue and only the loop takes work from it. With
+/// `ThreadPoolMode::WorkStealing` every worker has a queue of its own, submitted
+/// work is spread over them in turn, and a worker whose queue is empty steals
+/// from the others.
+pub(crate) struct SharedQueue {
+    queues: Vec<Mutex<WorkQueue>>,
+    // Submission counter, orders the work of different queues
+    submitted: AtomicU64,
+    // The most workers which may run CPU bound work at the same time
+    cpu_workers: usize,
+    // The workers running CPU bound work
+    cpu_running: AtomicUsize,
+    closed: AtomicBool,
+    // Workers without work wait on `wake` while holding `sleep`, producers only
+    // take `sleep` if `sleepers` says somebody waits
+    sleep: Mutex<()>,
+    wake: Condvar,
+    sleepers: AtomicUsize,
+}
+
+/// Locks `mutex`. Nothing panics while holding the queue locks, so a poisoned
+/// lock is still consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl SharedQueue {
+    pub(crate) fn new(policy: SchedulingPolicy, cpu_workers: usize, queues: usize) -> Self {
+        SharedQueue {
+            queues: (0..queues.max(1))
+                .map(|_| Mutex::new(WorkQueue::new(policy)))
+                .collect(),
+            submitted: AtomicU64::new(0),
+            cpu_workers,
+            cpu_running: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+            sleepers: AtomicUsize::new(0),
+        }
+    }
+
+    /// Queues `task` on the next queue in turn and wakes a worker waiting in
+    /// `take`.
+    pub(crate) fn push(&self, task: Task) {
+        let seq = self.submitted.fetch_add(1, Ordering::Relaxed) + 1;
+        let i = (seq % self.queues.len() as u64) as usize;
+        lock(&self.queues[i]).push(task, seq);
+        self.wake_one();
+    }
+
+    /// Takes the next work `worker` may run without waiting, from its own
+    /// queue first and otherwise from the queues of the other workers. CPU
+    /// bound work is skipped while `cpu_workers` workers are busy with it.
+    pub(crate) fn try_take(&self, worker: usize) -> Option<Task> {
+        let count = self.queues.len();
+        (0..count).find_map(|i| self.take_from(&self.queues[(worker + i) % count]))
+    }
+
+    fn take_from(&self, queue: &Mutex<WorkQueue>) -> Option<Task> {
+        let mut queue = lock(queue);
+        let cpu = self.cpu_running.load(Ordering::SeqCst) < self.cpu_workers;
+        let task = queue.pop(cpu)?;
+        if task.kind.lane() == Lane::Io {
+            return Some(task);
+        }
+
+        let claimed = self
+            .cpu_running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.cpu_workers).then_some(n + 1)
+            })
+            .is_ok();
+        if claimed {
+            Some(task)
+        } else {
+            // Another worker took the last slot in the meantime, it wakes a
+            // worker again once it's done
+            queue.push_next(task);
+            queue.pop(false)
+        }
+    }
+
+    /// Waits for the next work `worker` may run. Returns `None` once the pool
+    /// is closed.
+    pub(crate) fn take(&self, worker: usize) -> Option<Task> {
+        loop {
+            if self.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+            if let Some(task) = self.try_take(worker) {
+                return Some(task);
+            }
+
+            let sleeping = lock(&self.sleep);
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            // Pairs with the fence in `wake_one`: either the producer sees the
+            // sleeper, or this look sees the new work
+            fence(Ordering::SeqCst);
+            let task = self.try_take(worker);
+            if task.is_none() && !self.closed.load(Ordering::SeqCst) {
+                drop(self.wake.wait(sleeping).unwrap_or_else(|e| e.into_inner()));
+            }
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+            if task.is_some() {
+                return task;
+            }
+        }
+    }
+
+    /// Puts work which was taken but couldn't be handed to a worker back at
+    /// the head of the first queue, the only one with
+    /// `ThreadPoolMode::Dispatcher`, and gives its CPU slot back.
+    pub(crate) fn put_back(&self, task: Task) {
+        if task.kind.lane() == Lane::Cpu {
+            self.cpu_running.fetch_sub(1, Ordering::SeqCst);
+        }
+        lock(&self.queues[0]).push_next(task);
+        self.wake_one();
+    }
+
+    /// Gives the CPU slot of a worker which finished work of `lane` back.
+    pub(crate) fn finished(&self, lane: Lane) {
+        // Only a full pool kept workers from CPU bound work
+        if lane == Lane::Cpu && self.cpu_running.fetch_sub(1, Ordering::SeqCst) == self.cpu_workers
+        {
+            self.wake_one();
+        }
+    }
+
+    /// Removes the work which was submitted first, whichever queue holds it.
+    pub(crate) fn pop_oldest(&self) -> Option<Task> {
+        let (i, _) = (0..self.queues.len())
+            .filter_map(|i| Some((i, lock(&self.queues[i]).oldest()?)))
+            .min_by_key(|&(_, seq)| seq)?;
+        lock(&self.queues[i]).pop_oldest()
+    }
+
+    /// Removes the first queued work `f` returns `true` for.
+    pub(crate) fn remove(&self, f: impl Fn(&Task) -> bool) -> Option<Task> {
+        self.queues.iter().find_map(|queue| lock(queue).remove(&f))
+    }
+
+    /// The number of tasks in all queues.
+    pub(crate) fn len(&self) -> usize {
+        self.queues.iter().map(|queue| lock(queue).len()).sum()
+    }
+
+    /// Makes every worker waiting in `take` return `None`.
+    pub(crate) fn close(&self) {
+        let _sleeping = lock(&self.sleep);
+        self.closed.store(true, Ordering::SeqCst);
+        self.wake.notify_all();
+    }
+
+    /// Wakes a worker waiting in `take` to look for work again.
+    fn wake_one(&self) {
+        fence(Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _sleeping = lock(&self.sleep);
+            self.wake.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+fn pop_order(policy: SchedulingPolicy) -> Vec<usize> {
+    use crate::task::ThreadPoolTaskKind;
+
+    let kinds: Vec<(usize, ThreadPoolTaskKind)> = vec![
+        (1, ThreadPoolTaskKind::CalFibonacchi),
+        (2, ThreadPoolTaskKind::FileRead),
+        (3, ThreadPoolTaskKind::CalFibonacchi),
+        (4, ThreadPoolTaskKind::FileRead),
+    ];
+
+    let mut queue = WorkQueue::new(policy);
+    for (n, kind) in kinds {
+        queue.push(
+            Task {
+                task: Box::new(move || Box::new(n)),
+                callback_id: n,
+                kind,
+                cancelled: Default::default(),
+            },
+            n as u64,
+        );
+    }
+    assert_eq!(queue.len(), 4);
+
+    let mut order = vec![];
+    while let Some(work) = queue.pop(true) {
+        order.push(*(work.task)().downcast::<usize>().unwrap());
+    }
+    order
+}
+
+#[test]
+fn test_scheduling_policies() {
+    assert_eq!(pop_order(SchedulingPolicy::Fifo), vec![1, 2, 3, 4]);
+    assert_eq!(pop_order(SchedulingPolicy::Lifo), vec![4, 3, 2, 1]);
+    assert_eq!(pop_order(SchedulingPolicy::Priority), vec![2, 4, 1, 3]);
+}
+
+#[test]
+fn test_work_put_back_is_taken_next() {
+    use crate::task::ThreadPoolTaskKind;
+
+    for policy in [
+        SchedulingPolicy::Fifo,
+        SchedulingPolicy::Lifo,
+        SchedulingPolicy::Priority,
+    ] {
+        let mut queue = WorkQueue::new(policy);
+        for n in 1..=3 {
+            queue.push(
+                Task {
+                    task: Box::new(|| Box::new(())),
+                    callback_id: n,
+                    kind: ThreadPoolTaskKind::CalFibonacchi,
+                    cancelled: Default::default(),
+                },
+                n as u64,
+            );
+        }
+
+        let first = queue.pop(true).unwrap();
+        let id = first.callback_id;
+        queue.push_next(first);
+        assert_eq!(queue.pop(true).unwrap().callback_id, id, "{:?}", policy);
+    }
+}
+
+#[test]
+fn test_pop_skips_the_cpu_lane() {
+    use crate::task::ThreadPoolTaskKind;
+
+    let mut queue = WorkQueue::new(SchedulingPolicy::Fifo);
+    for (seq, kind) in (1..).zip([
+        ThreadPoolTaskKind::CalFibonacchi,
+        ThreadPoolTaskKind::FileRead,
+    ]) {
+        queue.push(
+            Task {
+                task: Box::new(|| Box::new(())),
+                callback_id: 0,
+                kind,
+                cancelled: Default::default(),
+            },
+            seq,
+        );
+    }
+
+    assert_eq!(queue.pop(false).unwrap().kind.lane(), Lane::Io);
+    assert!(queue.pop(false).is_none());
+    assert_eq!(queue.pop(true).unwrap().kind.lane(), Lane::Cpu);
+}
+
+#[test]
+fn test_shared_queue_limits_cpu_work() {
+    use crate::task::ThreadPoolTaskKind;
+
+    let queue = SharedQueue::new(SchedulingPolicy::Fifo, 1, 1);
+    for kind in [
+        ThreadPoolTaskKind::CalFibonacchi,
+        ThreadPoolTaskKind::CalFibonacchi,
+        ThreadPoolTaskKind::FileRead,
+    ] {
+        queue.push(Task {
+            task: Box::new(|| Box::new(())),
+            callback_id: 0,
+            kind,
+            cancelled: Default::default(),
+        });
+    }
+
+    assert_eq!(queue.try_take(0).unwrap().kind.lane(), Lane::Cpu);
+    assert_eq!(queue.try_take(0).unwrap().kind.lane(), Lane::Io);
+    assert!(queue.try_take(0).is_none());
+    queue.finished(Lane::Cpu);
+    assert_eq!(queue.try_take(0).unwrap().kind.lane(), Lane::Cpu);
+
+    queue.close();
+    assert!(queue.take(0).is_none());
+}
+
+#[test]
+fn test_workers_steal_when_their_queue_is_empty() {
+    use crate::task::ThreadPoolTaskKind;
+
+    let queue = SharedQueue::new(SchedulingPolicy::Fifo, 2, 2);
+    let push = |n| {
+        queue.push(Task {
+            task: Box::new(|| Box::new(())),
+            callback_id: n,
+            kind: ThreadPoolTaskKind::CalFibonacchi,
+            cancelled: Default::default(),
+        })
+    };
+    // Work is spread over both queues in turn
+    for n in 1..=4 {
+        push(n);
+    }
+    assert_eq!(queue.len(), 4);
+
+    // Worker 0 empties its own queue first, then steals from worker 1
+    assert_eq!(queue.pop_oldest().unwrap().callback_id, 1);
+    assert_eq!(queue.try_take(0).unwrap().callback_id, 2);
+    assert_eq!(queue.try_take(0).unwrap().callback_id, 4);
+    queue.finished(Lane::Cpu);
+    queue.finished(Lane::Cpu);
+    assert_eq!(queue.try_take(0).unwrap().callback_id, 3);
+    assert!(queue.try_take(1).is_none());
+}
+
+#[test]
+fn test_sleeping_workers_get_all_work() {
+    use crate::task::ThreadPoolTaskKind;
+    use std::{sync::Arc, thread};
+
+    let queue = Arc::new(SharedQueue::new(SchedulingPolicy::Fifo, 1, 4));
+    let workers: Vec<_> = (0..4)
+        .map(|worker| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let mut taken = 0;
+                while let Some(task) = queue.take(worker) {
+                    queue.finished(task.kind.lane());
+                    taken += 1;
+                }
+                taken
+            })
+        })
+        .collect();
+
+    for n in 0..1000 {
+        queue.push(Task {
+            task: Box::new(|| Box::new(())),
+            callback_id: n,
+            kind: ThreadPoolTaskKind::CalFibonacchi,
+            cancelled: Default::default(),
+        });
+    }
+    while queue.len() > 0 {
+        thread::yield_now();
+    }
+    queue.close();
+
+    let taken: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+    assert_eq!(taken, 1000);
+}
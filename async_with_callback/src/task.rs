@@ -1,96 +1,584 @@
 use std::{
+    cell::{Cell, RefCell},
+    error::Error,
     fmt, fs,
-    io::{Read, Write},
-    thread,
-    time::{Duration, Instant},
+    io::{self, Read, Write},
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
-use crate::ioresult::IOResult;
+use crate::handle::{self, RuntimeHandle};
+use crate::runtime::{Payload, ThreadPoolEvent};
+use crate::scheduler::Lane;
+use crate::timer::TimerId;
 use minimio;
 
 pub enum ThreadPoolTaskKind {
     Close,
     FileRead,
+    FileSystem,
     CalFibonacchi,
+    /// Work submitted with `spawn_blocking`, with the name it was given.
+    Blocking(&'static str),
 }
 
-impl fmt::Display for ThreadPoolTaskKind {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ThreadPoolTaskKind {
+    pub fn name(&self) -> &'static str {
         match self {
-            ThreadPoolTaskKind::Close => write!(f, "Close"),
-            ThreadPoolTaskKind::FileRead => write!(f, "FileRead"),
-            ThreadPoolTaskKind::CalFibonacchi => write!(f, "CalFibonacchi"),
+            ThreadPoolTaskKind::Close => "Close",
+            ThreadPoolTaskKind::FileRead => "FileRead",
+            ThreadPoolTaskKind::FileSystem => "FileSystem",
+            ThreadPoolTaskKind::CalFibonacchi => "CalFibonacchi",
+            ThreadPoolTaskKind::Blocking(name) => name,
         }
     }
+
+    /// Used by `SchedulingPolicy::Priority`, higher runs first. I/O bound
+    /// work is preferred over CPU bound work.
+    pub fn priority(&self) -> u8 {
+        match self {
+            ThreadPoolTaskKind::Close => 0,
+            ThreadPoolTaskKind::FileRead => 1,
+            ThreadPoolTaskKind::FileSystem => 1,
+            ThreadPoolTaskKind::CalFibonacchi => 0,
+            ThreadPoolTaskKind::Blocking(_) => 0,
+        }
+    }
+
+    /// The lane of the thread pool queue this kind of work waits in.
+    pub fn lane(&self) -> Lane {
+        match self {
+            ThreadPoolTaskKind::FileRead | ThreadPoolTaskKind::FileSystem => Lane::Io,
+            ThreadPoolTaskKind::Close
+            | ThreadPoolTaskKind::CalFibonacchi
+            | ThreadPoolTaskKind::Blocking(_) => Lane::Cpu,
+        }
+    }
+}
+
+impl fmt::Display for ThreadPoolTaskKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
 
 pub struct Task {
-    pub(crate) task: Box<dyn Fn() -> IOResult + Send + 'static>,
+    pub(crate) task: Box<dyn FnOnce() -> Payload + Send + 'static>,
     pub(crate) callback_id: usize,
     pub(crate) kind: ThreadPoolTaskKind,
+    pub(crate) cancelled: Arc<AtomicBool>,
 }
 
 impl Task {
     pub fn close() -> Self {
         Task {
-            task: Box::new(|| IOResult::Undefined),
+            task: Box::new(|| Box::new(())),
             callback_id: 0,
             kind: ThreadPoolTaskKind::Close,
+            cancelled: Arc::default(),
         }
     }
 }
 
+thread_local! {
+    // The cancellation flag of the task running on this worker thread
+    static CANCELLED: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Returns `true` if the work running on the current worker thread was
+/// cancelled or timed out. Long running work can check this and return early,
+/// its result is discarded anyway. Always `false` outside of the thread pool.
+pub fn is_cancelled() -> bool {
+    CANCELLED.with(|cancelled| {
+        cancelled
+            .borrow()
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::SeqCst))
+    })
+}
+
+/// Sets the cancellation flag `is_cancelled` reads on this worker thread.
+pub(crate) fn set_cancel_flag(flag: Option<Arc<AtomicBool>>) {
+    CANCELLED.with(|cancelled| *cancelled.borrow_mut() = flag);
+}
+
+/// Why work on the thread pool didn't deliver its result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskError {
+    /// The work was cancelled through its `WorkHandle`.
+    Cancelled,
+    /// The deadline set with `WorkHandle::timeout` expired first.
+    TimedOut,
+    /// The work didn't fit into the thread pool queue, see
+    /// `OverflowPolicy`.
+    QueueFull,
+    /// No worker thread is left to run the work, because every worker failed
+    /// to start, see `RuntimeBuilder::on_thread_start`.
+    NoWorkers,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskError::Cancelled => write!(f, "the task was cancelled"),
+            TaskError::TimedOut => write!(f, "the task timed out"),
+            TaskError::QueueFull => write!(f, "the thread pool queue is full"),
+            TaskError::NoWorkers => write!(f, "no worker thread is left to run the task"),
+        }
+    }
+}
+
+impl Error for TaskError {}
+
+/// Tasks which report an `io::Result`, like `Fs`, wrap the `TaskError` in an
+/// `io::Error`. A timeout has the kind `TimedOut`, everything else `Other`.
+impl From<TaskError> for io::Error {
+    fn from(e: TaskError) -> Self {
+        match e {
+            TaskError::TimedOut => io::Error::new(io::ErrorKind::TimedOut, e),
+            TaskError::Cancelled | TaskError::QueueFull | TaskError::NoWorkers => {
+                io::Error::other(e)
+            }
+        }
+    }
+}
+
+/// Returned for every piece of work submitted to the thread pool, to cancel
+/// it or give it a deadline.
+pub struct WorkHandle {
+    cancelled: Arc<AtomicBool>,
+    // Calls the callback with the error, unless it was called before
+    fail: Rc<dyn Fn(TaskError)>,
+    // Set once the callback was called
+    finished: Rc<Cell<bool>>,
+    // The timer of the deadline set with `timeout`
+    deadline: Rc<Cell<Option<TimerId>>>,
+    // The work was turned away by `OverflowPolicy::Busy`
+    busy: bool,
+    runtime: RuntimeHandle,
+}
+
+impl WorkHandle {
+    /// Cancels the work, its callback is called with `TaskError::Cancelled`
+    /// in the next check phase.
+    ///
+    /// Work which is still queued is removed from the queue. Work which is
+    /// already running can't be stopped, but `is_cancelled` returns `true` on
+    /// its worker thread so it can stop early, and its result is discarded.
+    /// Cancelling after the callback ran does nothing.
+    pub fn cancel(&self) {
+        if self.finished.get() || self.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let fail = self.fail.clone();
+        let _ = self.runtime.with(|rt| {
+            rt.cancel_queued(&self.cancelled);
+            rt.set_immediate(move || fail(TaskError::Cancelled));
+        });
+    }
+
+    /// Gives the work `ms` milliseconds from now to deliver its result. When
+    /// the deadline expires first, the callback is called with
+    /// `TaskError::TimedOut` in the timers phase and the work is stopped like
+    /// a cancelled one. Calling this again replaces the deadline.
+    pub fn timeout(self, ms: u64) -> Self {
+        if self.finished.get() || self.is_cancelled() {
+            return self;
+        }
+
+        let cancelled = self.cancelled.clone();
+        let fail = self.fail.clone();
+        let deadline = self.deadline.clone();
+        let runtime = self.runtime.clone();
+        let timer = self.runtime.with(|rt| {
+            if let Some(id) = self.deadline.take() {
+                rt.clear_timer(id);
+            }
+            rt.set_timeout(ms, move || {
+                deadline.set(None);
+                if cancelled.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                let _ = runtime.with(|rt| rt.cancel_queued(&cancelled));
+                fail(TaskError::TimedOut);
+            })
+        });
+        if let Ok(id) = timer {
+            self.deadline.set(Some(id));
+        }
+        self
+    }
+
+    /// Returns `true` once the work was cancelled or timed out.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if the thread pool queue was full and the work was not
+    /// accepted, see `OverflowPolicy::Busy`. The callback of such work is
+    /// never called.
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+}
+
+/// Runs `work` on the thread pool and calls `cb` with its result on the
+/// event loop. Use it for blocking or CPU bound work which would otherwise
+/// stall the loop. `name` shows up as the task kind in the logs.
+///
+/// ```no_run
+/// use async_with_callback::{runtime::Runtime, task::spawn_blocking};
+///
+/// Runtime::new().run(|| {
+///     let handle = spawn_blocking("checksum", || (1..=100u64).sum::<u64>(), |sum| {
+///         match sum {
+///             Ok(sum) => println!("checksum: {}", sum),
+///             Err(e) => println!("checksum failed: {}", e),
+///         }
+///     });
+///     handle.cancel();
+/// });
+/// ```
+pub fn spawn_blocking<T, W, C>(name: &'static str, work: W, cb: C) -> WorkHandle
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+    C: FnOnce(Result<T, TaskError>) + 'static,
+{
+    spawn(ThreadPoolTaskKind::Blocking(name), work, cb)
+}
+
+/// Queues `work` for the thread pool. The result travels to the loop as a
+/// `Payload` and is downcast back to `T` before `cb` sees it.
+pub(crate) fn spawn<T, W, C>(kind: ThreadPoolTaskKind, work: W, cb: C) -> WorkHandle
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+    C: FnOnce(Result<T, TaskError>) + 'static,
+{
+    let runtime = RuntimeHandle::current();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let finished = Rc::new(Cell::new(false));
+    let deadline: Rc<Cell<Option<TimerId>>> = Rc::new(Cell::new(None));
+
+    // Whatever happens first takes `cb`: the result, a cancellation, the
+    // deadline or a panic of the task. The deadline isn't needed anymore
+    // after that.
+    let finish = {
+        let cb = RefCell::new(Some(cb));
+        let (finished, deadline) = (finished.clone(), deadline.clone());
+        let runtime = runtime.clone();
+        Rc::new(move || {
+            let cb = cb.borrow_mut().take();
+            if cb.is_some() {
+                finished.set(true);
+                if let Some(id) = deadline.take() {
+                    let _ = runtime.with(|rt| rt.clear_timer(id));
+                }
+            }
+            cb
+        })
+    };
+    let complete = {
+        let finish = finish.clone();
+        Rc::new(move |res: Result<T, TaskError>| {
+            if let Some(cb) = finish() {
+                cb(res);
+            }
+        })
+    };
+    let fail: Rc<dyn Fn(TaskError)> = {
+        let complete = complete.clone();
+        Rc::new(move |e| complete(Err(e)))
+    };
+    // A task which panicked never calls `cb`
+    let discard: Rc<dyn Fn()> = Rc::new(move || drop(finish()));
+
+    let work = move || Box::new(work()) as Payload;
+    // The result of cancelled work is ignored, its callback is called by
+    // `fail` instead.
+    let flag = cancelled.clone();
+    let cb = move |res: Payload| {
+        if flag.load(Ordering::SeqCst) {
+            return;
+        }
+        match res.downcast::<T>() {
+            Ok(res) => complete(Ok(*res)),
+            Err(_) => unreachable!("the result of a task always has the type of its callback"),
+        }
+    };
+
+    let busy = runtime
+        .with(|rt| {
+            rt.queue_work(ThreadPoolEvent {
+                task: Box::new(work),
+                kind,
+                callback: Box::new(cb),
+                cancelled: cancelled.clone(),
+                fail: fail.clone(),
+                discard,
+            })
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    WorkHandle {
+        cancelled,
+        fail,
+        finished,
+        deadline,
+        busy,
+        runtime,
+    }
+}
+
+/// File system operations which run on the thread pool, like Node's `fs`
+/// module. Every callback receives an `io::Result` with the outcome of the
+/// operation.
 pub struct Fs;
 impl Fs {
-    pub fn read(path: &'static str, cb: impl Fn(IOResult) + 'static) {
-        let work = move || {
-            thread::sleep(std::time::Duration::from_secs(2));
-            let mut buffer = String::new();
-            fs::File::open(&path)
-                .unwrap()
-                .read_to_string(&mut buffer)
-                .unwrap();
-            IOResult::String(buffer)
-        };
+    /// Reads the whole file as UTF-8. Use `read_bytes` for binary files.
+    pub fn read(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<String>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileRead, cb, move || {
+            fs::read_to_string(&path)
+        })
+    }
 
-        let rt = unsafe { &mut *crate::runtime::RUNTIME };
-        rt.thread_pool_event
-            .push((Box::new(work), ThreadPoolTaskKind::FileRead, Box::new(cb)));
+    /// Reads the whole file.
+    pub fn read_bytes(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<Vec<u8>>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileRead, cb, move || fs::read(&path))
+    }
+
+    /// Creates or truncates the file and writes `contents` to it.
+    pub fn write(
+        path: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+        cb: impl FnOnce(io::Result<()>) + 'static,
+    ) -> WorkHandle {
+        let (path, contents) = (path.into(), contents.into());
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::write(&path, &contents)
+        })
+    }
+
+    /// Appends `contents` to the file, creating it if it doesn't exist.
+    pub fn append(
+        path: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+        cb: impl FnOnce(io::Result<()>) + 'static,
+    ) -> WorkHandle {
+        let (path, contents) = (path.into(), contents.into());
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(&contents))
+        })
+    }
+
+    /// Calls `cb` with the paths of the entries of the directory, sorted by
+    /// name.
+    pub fn read_dir(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<Vec<PathBuf>>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            let mut paths = fs::read_dir(&path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            paths.sort();
+            Ok(paths)
+        })
+    }
+
+    /// Calls `cb` with the metadata of the file or directory. Symbolic links
+    /// are followed.
+    pub fn metadata(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<fs::Metadata>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::metadata(&path)
+        })
+    }
+
+    /// Removes the file.
+    pub fn remove_file(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<()>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::remove_file(&path)
+        })
+    }
+
+    /// Renames `from` to `to`, replacing `to` if it exists.
+    pub fn rename(
+        from: impl Into<PathBuf>,
+        to: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<()>) + 'static,
+    ) -> WorkHandle {
+        let (from, to) = (from.into(), to.into());
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::rename(&from, &to)
+        })
+    }
+
+    /// Creates the directory and all of its missing parents.
+    pub fn create_dir_all(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<()>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::create_dir_all(&path)
+        })
+    }
+
+    /// Copies the contents of `from` to `to`. Calls `cb` with the number of
+    /// bytes copied.
+    pub fn copy(
+        from: impl Into<PathBuf>,
+        to: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<u64>) + 'static,
+    ) -> WorkHandle {
+        let (from, to) = (from.into(), to.into());
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            fs::copy(&from, &to)
+        })
+    }
+
+    /// Calls `cb` with `true` if the path exists. Errors other than a missing
+    /// path, like a permission error, are passed on.
+    pub fn exists(
+        path: impl Into<PathBuf>,
+        cb: impl FnOnce(io::Result<bool>) + 'static,
+    ) -> WorkHandle {
+        let path = path.into();
+        Self::spawn(ThreadPoolTaskKind::FileSystem, cb, move || {
+            path.try_exists()
+        })
+    }
+
+    fn spawn<T: Send + 'static>(
+        kind: ThreadPoolTaskKind,
+        cb: impl FnOnce(io::Result<T>) + 'static,
+        work: impl FnOnce() -> io::Result<T> + Send + 'static,
+    ) -> WorkHandle {
+        spawn(kind, work, move |res| {
+            cb(res.map_err(io::Error::from).and_then(|res| res))
+        })
     }
 }
 
 pub struct Fibonacchi;
 impl Fibonacchi {
-    pub fn cal(n: usize, cb: impl Fn(IOResult) + 'static) {
+    /// Calculates the `n`th fibonacchi number on the thread pool. Fails with
+    /// `io::ErrorKind::InvalidInput` if the result doesn't fit in a `usize`.
+    pub fn cal(n: usize, cb: impl FnOnce(io::Result<usize>) + 'static) -> WorkHandle {
         let work = move || {
-            fn fibonacchi(n: usize) -> usize {
+            fn fibonacchi(n: usize) -> Option<usize> {
                 match n {
-                    0 => 0,
-                    1 => 1,
-                    _ => fibonacchi(n - 1) + fibonacchi(n - 2),
+                    0 => Some(0),
+                    1 => Some(1),
+                    _ => fibonacchi(n - 1)?.checked_add(fibonacchi(n - 2)?),
                 }
-            };
+            }
 
-            IOResult::Int(fibonacchi(n))
+            fibonacchi(n).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("fibonacchi({}) overflows usize", n),
+                )
+            })
         };
 
-        let rt = unsafe { &mut *crate::runtime::RUNTIME };
-        rt.thread_pool_event.push((
-            Box::new(work),
-            ThreadPoolTaskKind::CalFibonacchi,
-            Box::new(cb),
-        ));
+        spawn(ThreadPoolTaskKind::CalFibonacchi, work, move |res| {
+            cb(res.map_err(io::Error::from).and_then(|res| res))
+        })
+    }
+}
+
+/// A request waiting for its event on the epoll thread. The request keeps the
+/// runtime alive until its callback was called, unless it is unreferenced.
+#[derive(Clone)]
+pub struct IoHandle {
+    token: usize,
+    runtime: RuntimeHandle,
+}
+
+impl IoHandle {
+    /// Stops the request from keeping the runtime alive. If nothing else
+    /// keeps it running, the loop finishes without calling the callback.
+    pub fn unref(&self) {
+        let _ = self.runtime.with(|rt| rt.set_epoll_ref(self.token, false));
+    }
+
+    /// Makes the request keep the runtime alive again after `unref`.
+    pub fn ref_(&self) {
+        let _ = self.runtime.with(|rt| rt.set_epoll_ref(self.token, true));
+    }
+
+    /// Returns `true` if the request is waiting and keeps the runtime alive.
+    pub fn has_ref(&self) -> bool {
+        self.runtime
+            .with(|rt| rt.epoll_has_ref(self.token))
+            .unwrap_or(false)
     }
 }
 
 pub struct Http;
 impl Http {
-    pub fn http_get_slow(url: &str, delay_ms: u32, cb: impl Fn(IOResult) + 'static + Clone) {
-        let rt = unsafe { &mut *crate::runtime::RUNTIME };
+    /// Sends a GET request through the slowwly proxy which delays the response
+    /// by `delay_ms`, and calls `cb` with the response. A response which isn't
+    /// valid UTF-8 fails with `io::ErrorKind::InvalidData`, use
+    /// `http_get_slow_bytes` for binary content.
+    pub fn http_get_slow(
+        url: &str,
+        delay_ms: u32,
+        cb: impl FnOnce(io::Result<String>) + 'static,
+    ) -> IoHandle {
+        Self::get_slow(url, delay_ms, move |res| {
+            cb(res.and_then(|bytes| {
+                String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }))
+        })
+    }
 
-        let adr = "slowwly.robertomurray.co.uk:80";
-        let mut stream = minimio::TcpStream::connect(adr).unwrap();
+    /// Like `http_get_slow`, but calls `cb` with the raw response.
+    pub fn http_get_slow_bytes(
+        url: &str,
+        delay_ms: u32,
+        cb: impl FnOnce(io::Result<Vec<u8>>) + 'static,
+    ) -> IoHandle {
+        Self::get_slow(url, delay_ms, cb)
+    }
 
+    /// If connecting or registering the stream fails, `cb` is called with the
+    /// error in the check phase.
+    // `&mut` is needed by the windows `Registrator`
+    #[allow(clippy::unnecessary_mut_passed)]
+    fn get_slow(
+        url: &str,
+        delay_ms: u32,
+        cb: impl FnOnce(io::Result<Vec<u8>>) + 'static,
+    ) -> IoHandle {
+        let adr = "slowwly.robertomurray.co.uk:80";
         let request = format!(
             "GET /delay/{}/url/http://{} HTTP/1.1\r\n\
              Host: slowwly.robertomurray.co.uk\r\n\
@@ -99,32 +587,114 @@ impl Http {
             delay_ms, url
         );
 
-        stream
-            .write_all(request.as_bytes())
-            .expect("Error writing to stream");
-
-        let token = rt.generate_cb_identity();
-        rt.epoll_registrator
-            .register(&mut stream, token, minimio::Interests::READABLE)
-            .unwrap();
-
-        let wrapped = move |_n| {
-            let mut stream = stream;
-            let mut buffer = String::new();
-            stream
-                .read_to_string(&mut buffer)
-                .expect("Stream read error");
-            cb(IOResult::String(buffer));
-        };
+        let connected = minimio::TcpStream::connect(adr).and_then(|mut stream| {
+            stream.write_all(request.as_bytes())?;
+            Ok(stream)
+        });
+
+        let token = handle::with_current(|rt| {
+            let token = rt.generate_cb_identity();
+            let mut stream = match connected {
+                Ok(stream) => stream,
+                Err(e) => {
+                    rt.set_immediate(move || cb(Err(e)));
+                    return token;
+                }
+            };
+
+            let registered =
+                rt.epoll_registrator
+                    .register(&mut stream, token, minimio::Interests::READABLE);
+            if let Err(e) = registered {
+                rt.set_immediate(move || cb(Err(e)));
+                return token;
+            }
+
+            let wrapped = move || {
+                let mut stream = stream;
+                let mut buffer = vec![];
+                cb(stream.read_to_end(&mut buffer).map(|_| buffer));
+            };
 
-        rt.register_epoll_event(token, wrapped);
+            rt.register_epoll_event(token, wrapped);
+            token
+        });
+
+        IoHandle {
+            token,
+            runtime: RuntimeHandle::current(),
+        }
     }
 }
 
 pub struct Timeout;
 impl Timeout {
-    pub fn set_timeout(ms: u64, cb: impl Fn(IOResult) + 'static) {
-        let rt = unsafe { &mut *crate::runtime::RUNTIME };
-        rt.set_timeout(ms, cb);
+    /// Runs `cb` once after `ms` milliseconds.
+    pub fn set_timeout(ms: u64, cb: impl Fn() + 'static) -> TimerId {
+        handle::with_current(|rt| rt.set_timeout(ms, cb))
+    }
+
+    /// Prevents a timeout from running. Returns `false` if it already ran or
+    /// was cleared before.
+    pub fn clear_timeout(id: TimerId) -> bool {
+        handle::with_current(|rt| rt.clear_timer(id))
+    }
+
+    /// Runs `cb` every `ms` milliseconds until the interval is cleared. An
+    /// active interval keeps the runtime alive.
+    pub fn set_interval(ms: u64, cb: impl Fn() + 'static) -> TimerId {
+        handle::with_current(|rt| rt.set_interval(ms, cb))
+    }
+
+    /// Stops an interval. Returns `false` if it was cleared before.
+    pub fn clear_interval(id: TimerId) -> bool {
+        handle::with_current(|rt| rt.clear_timer(id))
+    }
+
+    /// Stops a timeout or an interval from keeping the runtime alive, like
+    /// Node's `timeout.unref()`. It still fires while the loop runs for other
+    /// reasons. Returns `false` if the timer already fired or was cleared.
+    pub fn unref(id: TimerId) -> bool {
+        handle::with_current(|rt| rt.set_timer_ref(id, false))
+    }
+
+    /// Makes a timer keep the runtime alive again after `unref`. Returns
+    /// `false` if the timer already fired or was cleared.
+    pub fn ref_(id: TimerId) -> bool {
+        handle::with_current(|rt| rt.set_timer_ref(id, true))
+    }
+
+    /// Returns `true` if the timer is waiting and keeps the runtime alive.
+    pub fn has_ref(id: TimerId) -> bool {
+        handle::with_current(|rt| rt.timer_has_ref(id))
+    }
+}
+
+pub struct Immediate;
+impl Immediate {
+    /// Runs `cb` in the check phase of the current loop iteration, right
+    /// after the poll phase.
+    pub fn set_immediate(cb: impl FnOnce() + 'static) {
+        handle::with_current(|rt| rt.set_immediate(cb));
+    }
+}
+
+pub struct Process;
+impl Process {
+    /// Runs `cb` as soon as the current callback returned, before the loop
+    /// continues with the next callback or phase.
+    pub fn next_tick(cb: impl FnOnce() + 'static) {
+        handle::with_current(|rt| rt.next_tick(cb));
+    }
+
+    /// Sets the exit code `Runtime::run` reports when the loop is done, like
+    /// Node's `process.exitCode`. Doesn't stop the loop.
+    pub fn set_exit_code(code: i32) {
+        handle::with_current(|rt| rt.set_exit_code(code));
+    }
+
+    /// The exit code set with `set_exit_code`, 0 if none was set.
+    pub fn exit_code() -> i32 {
+        handle::with_current(|rt| rt.exit_code())
     }
 }
@@ -0,0 +1,238 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// The shortest interval of a repeating timer, so an interval of 0 can't keep
+/// the timers phase busy forever.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Identifies a timer set with `Timeout::set_timeout` or
+/// `Timeout::set_interval`. Used to clear the timer again. The id belongs to
+/// the runtime which set the timer, other runtimes don't know it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId {
+    runtime: u64,
+    seq: u64,
+}
+
+impl TimerId {
+    /// The number of the timer, unique within its runtime.
+    pub fn as_u64(&self) -> u64 {
+        self.seq
+    }
+}
+
+/// Timers are keyed by their deadline and a sequence number, so timers with
+/// the same deadline are all kept and run in the order they were set.
+type TimerKey = (Instant, u64);
+
+pub(crate) type TimerCallback = Rc<dyn Fn()>;
+
+struct Timer {
+    key: TimerKey,
+    interval: Option<Duration>,
+    callback: TimerCallback,
+    // Whether the timer keeps the loop alive
+    referenced: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct Timers {
+    // The id of the runtime the timers belong to
+    runtime: u64,
+    queue: BTreeMap<TimerKey, TimerId>,
+    timers: HashMap<TimerId, Timer>,
+    seq: u64,
+    // The number of referenced timers
+    referenced: usize,
+}
+
+impl Timers {
+    pub(crate) fn new(runtime: u64) -> Self {
+        Timers {
+            runtime,
+            ..Timers::default()
+        }
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        deadline: Instant,
+        interval: Option<Duration>,
+        callback: TimerCallback,
+    ) -> TimerId {
+        self.seq += 1;
+        let id = TimerId {
+            runtime: self.runtime,
+            seq: self.seq,
+        };
+        let key = (deadline, self.seq);
+        self.queue.insert(key, id);
+        self.timers.insert(
+            id,
+            Timer {
+                key,
+                interval: interval.map(|i| i.max(MIN_INTERVAL)),
+                callback,
+                referenced: true,
+            },
+        );
+        self.referenced += 1;
+        id
+    }
+
+    /// Removes the timer. Returns `false` if it already fired or was removed.
+    pub(crate) fn remove(&mut self, id: TimerId) -> bool {
+        match self.timers.remove(&id) {
+            Some(timer) => {
+                self.queue.remove(&timer.key);
+                self.referenced -= timer.referenced as usize;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes the first timer which expired at `now`. Repeating timers are
+    /// scheduled again, the returned flag is `true` for them.
+    pub(crate) fn pop_expired(&mut self, now: Instant) -> Option<(TimerCallback, bool)> {
+        let (&key, &id) = self.queue.range(..=(now, u64::MAX)).next()?;
+        self.queue.remove(&key);
+
+        let interval = self.timers[&id].interval;
+        match interval {
+            Some(interval) => {
+                self.seq += 1;
+                let key = (now + interval, self.seq);
+                self.queue.insert(key, id);
+                let timer = self.timers.get_mut(&id).unwrap();
+                timer.key = key;
+                Some((timer.callback.clone(), true))
+            }
+            None => {
+                let timer = self.timers.remove(&id).unwrap();
+                self.referenced -= timer.referenced as usize;
+                Some((timer.callback, false))
+            }
+        }
+    }
+
+    /// Removes every timer. Returns how many there were.
+    pub(crate) fn clear(&mut self) -> usize {
+        let count = self.timers.len();
+        self.timers.clear();
+        self.queue.clear();
+        self.referenced = 0;
+        count
+    }
+
+    /// Sets whether the timer keeps the loop alive. Returns `false` if the
+    /// timer already fired or was removed.
+    pub(crate) fn set_ref(&mut self, id: TimerId, referenced: bool) -> bool {
+        match self.timers.get_mut(&id) {
+            Some(timer) => {
+                if timer.referenced != referenced {
+                    timer.referenced = referenced;
+                    if referenced {
+                        self.referenced += 1;
+                    } else {
+                        self.referenced -= 1;
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the timer is waiting and keeps the loop alive.
+    pub(crate) fn has_ref(&self, id: TimerId) -> bool {
+        self.timers.get(&id).is_some_and(|timer| timer.referenced)
+    }
+
+    /// The number of timers which keep the loop alive.
+    pub(crate) fn referenced(&self) -> usize {
+        self.referenced
+    }
+
+    /// Returns the deadline of the timer which expires next.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.queue.keys().next().map(|&(deadline, _)| deadline)
+    }
+}
+
+#[test]
+fn test_timers_with_same_deadline() {
+    use std::cell::RefCell;
+
+    let fired = Rc::new(RefCell::new(vec![]));
+    let mut timers = Timers::default();
+    let deadline = Instant::now();
+    for i in 0..3 {
+        let fired = fired.clone();
+        timers.insert(deadline, None, Rc::new(move || fired.borrow_mut().push(i)));
+    }
+    assert_eq!(timers.timers.len(), 3);
+
+    while let Some((cb, repeat)) = timers.pop_expired(deadline) {
+        assert!(!repeat);
+        cb();
+    }
+    assert!(timers.timers.is_empty() && timers.queue.is_empty());
+    assert_eq!(*fired.borrow(), vec![0, 1, 2]);
+}
+
+#[test]
+fn test_interval_is_rescheduled_until_removed() {
+    let mut timers = Timers::default();
+    let start = Instant::now();
+    let id = timers.insert(start, Some(Duration::from_millis(10)), Rc::new(|| ()));
+
+    assert!(timers.pop_expired(start).unwrap().1);
+    assert!(timers.pop_expired(start).is_none());
+    assert_eq!(
+        timers.next_deadline(),
+        Some(start + Duration::from_millis(10))
+    );
+
+    assert!(timers.remove(id));
+    assert!(!timers.remove(id));
+    assert!(timers.next_deadline().is_none());
+}
+
+#[test]
+fn test_unreferenced_timers_are_not_counted() {
+    let mut timers = Timers::default();
+    let now = Instant::now();
+    let once = timers.insert(now, None, Rc::new(|| ()));
+    let interval = timers.insert(now, Some(Duration::from_millis(10)), Rc::new(|| ()));
+    assert_eq!(timers.referenced(), 2);
+
+    assert!(timers.set_ref(once, false));
+    assert!(timers.set_ref(once, false));
+    assert!(!timers.has_ref(once));
+    assert_eq!(timers.referenced(), 1);
+
+    // Firing or removing an unreferenced timer doesn't change the count
+    assert!(!timers.pop_expired(now).unwrap().1);
+    assert_eq!(timers.referenced(), 1);
+    assert!(!timers.set_ref(once, true));
+    assert!(timers.remove(interval));
+    assert_eq!(timers.referenced(), 0);
+}
+
+#[test]
+fn test_timer_ids_belong_to_their_runtime() {
+    let mut first = Timers::new(1);
+    let mut second = Timers::new(2);
+    let now = Instant::now();
+    let id = first.insert(now, None, Rc::new(|| ()));
+    second.insert(now, None, Rc::new(|| ()));
+
+    assert!(!second.remove(id));
+    assert!(!second.set_ref(id, false));
+    assert_eq!(second.referenced(), 1);
+    assert!(first.remove(id));
+}
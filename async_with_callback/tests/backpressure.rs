@@ -0,0 +1,69 @@
+use async_with_callback::{
+    handle::RuntimeHandle,
+    runtime::Runtime,
+    scheduler::OverflowPolicy,
+    task::{spawn_blocking, TaskError},
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// Submits five tasks to a queue with room for two and returns what their
+/// callbacks received, the tasks which were turned away as busy, and the
+/// queue depth after submitting.
+fn submit_burst(policy: OverflowPolicy) -> (Vec<Result<usize, TaskError>>, Vec<usize>, usize) {
+    let results = Rc::new(RefCell::new(vec![]));
+    let busy = Rc::new(RefCell::new(vec![]));
+    let depth = Rc::new(RefCell::new(0));
+
+    let (results_clone, busy_clone, depth_clone) = (results.clone(), busy.clone(), depth.clone());
+    Runtime::builder()
+        .worker_threads(1)
+        .max_queued_work(2)
+        .overflow_policy(policy)
+        .build()
+        .run(move || {
+            for n in 1..=5 {
+                let results = results_clone.clone();
+                let handle = spawn_blocking(
+                    "burst",
+                    move || n,
+                    move |res| results.borrow_mut().push(res),
+                );
+                if handle.is_busy() {
+                    busy_clone.borrow_mut().push(n);
+                }
+            }
+            *depth_clone.borrow_mut() = RuntimeHandle::current().queue_depth();
+        });
+
+    let mut results = results.borrow().clone();
+    results.sort_by_key(|res| *res.as_ref().unwrap_or(&0));
+    let busy = busy.borrow().clone();
+    let depth = *depth.borrow();
+    (results, busy, depth)
+}
+
+#[test]
+fn reject_fails_the_new_work() {
+    let (results, busy, depth) = submit_burst(OverflowPolicy::Reject);
+    let full = Err(TaskError::QueueFull);
+    assert_eq!(results, vec![full, full, full, Ok(1), Ok(2)]);
+    assert!(busy.is_empty());
+    assert_eq!(depth, 2);
+}
+
+#[test]
+fn drop_oldest_fails_the_queued_work() {
+    let (results, busy, depth) = submit_burst(OverflowPolicy::DropOldest);
+    let full = Err(TaskError::QueueFull);
+    assert_eq!(results, vec![full, full, full, Ok(4), Ok(5)]);
+    assert!(busy.is_empty());
+    assert_eq!(depth, 2);
+}
+
+#[test]
+fn busy_turns_the_new_work_away() {
+    let (results, busy, depth) = submit_burst(OverflowPolicy::Busy);
+    assert_eq!(results, vec![Ok(1), Ok(2)]);
+    assert_eq!(busy, vec![3, 4, 5]);
+    assert_eq!(depth, 2);
+}
@@ -0,0 +1,47 @@
+use async_with_callback::{builder::RuntimeBuilder, runtime::Runtime, task::Fibonacchi};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[test]
+fn worker_hooks_and_names() {
+    let started = Arc::new(Mutex::new(vec![]));
+    let stopped = Arc::new(Mutex::new(vec![]));
+
+    let started_clone = started.clone();
+    let stopped_clone = stopped.clone();
+    let runtime = Runtime::builder()
+        .worker_threads(2)
+        .thread_name("test-worker")
+        .thread_stack_size(256 * 1024)
+        .on_thread_start(move |id| {
+            let name = thread::current().name().unwrap().to_string();
+            started_clone.lock().unwrap().push((id, name));
+        })
+        .on_thread_stop(move |id| stopped_clone.lock().unwrap().push(id))
+        .build();
+
+    runtime.run(|| {
+        Fibonacchi::cal(10, |res| assert_eq!(res.unwrap(), 55));
+    });
+
+    let mut started = started.lock().unwrap().clone();
+    started.sort();
+    assert_eq!(
+        started,
+        vec![
+            (0, "test-worker-0".to_string()),
+            (1, "test-worker-1".to_string())
+        ]
+    );
+    let mut stopped = stopped.lock().unwrap().clone();
+    stopped.sort();
+    assert_eq!(stopped, vec![0, 1]);
+}
+
+#[test]
+fn worker_count_prefers_worker_threads() {
+    assert_eq!(RuntimeBuilder::new().worker_threads(5).worker_count(), 5);
+    assert!(RuntimeBuilder::new().worker_count() > 0);
+}
@@ -0,0 +1,131 @@
+use async_with_callback::{
+    runtime::Runtime,
+    task::{self, spawn_blocking, Fibonacchi, Immediate, TaskError, Timeout, WorkHandle},
+};
+use std::{
+    cell::RefCell,
+    io,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn cancel_queued_and_running_work() {
+    let results = Rc::new(RefCell::new(vec![]));
+    let start = Instant::now();
+
+    let results_clone = results.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        // Occupies the only worker until it sees the cancellation
+        let results = results_clone.clone();
+        let running = spawn_blocking(
+            "spin",
+            || {
+                while !task::is_cancelled() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                "stopped"
+            },
+            move |res| results.borrow_mut().push(format!("running: {:?}", res)),
+        );
+
+        let results = results_clone.clone();
+        let queued = Fibonacchi::cal(10, move |res| {
+            let e = res.unwrap_err();
+            let e = e.get_ref().and_then(|e| e.downcast_ref::<TaskError>());
+            results.borrow_mut().push(format!("queued: {:?}", e));
+        });
+
+        Timeout::set_timeout(10, move || {
+            queued.cancel();
+            running.cancel();
+            assert!(queued.is_cancelled() && running.is_cancelled());
+        });
+    });
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(
+        *results.borrow(),
+        vec![
+            "queued: Some(Cancelled)".to_string(),
+            "running: Err(Cancelled)".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn deadlines_time_out_running_and_queued_work() {
+    let results = Rc::new(RefCell::new(vec![]));
+    let start = Instant::now();
+
+    let results_clone = results.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        let results = results_clone.clone();
+        spawn_blocking(
+            "sleep",
+            || thread::sleep(Duration::from_millis(200)),
+            move |res| {
+                results.borrow_mut().push(format!("running: {:?}", res));
+            },
+        )
+        .timeout(20);
+
+        let results = results_clone.clone();
+        Fibonacchi::cal(10, move |res| {
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+            results.borrow_mut().push("queued: TimedOut".to_string());
+        })
+        .timeout(10);
+    });
+
+    // The loop still waited for the late result of the running work
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!(
+        *results.borrow(),
+        vec![
+            "queued: TimedOut".to_string(),
+            "running: Err(TimedOut)".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn deadline_is_cleared_when_work_finishes_in_time() {
+    let result = Rc::new(RefCell::new(None));
+    let start = Instant::now();
+
+    let result_clone = result.clone();
+    Runtime::new().run(move || {
+        let result = result_clone.clone();
+        Fibonacchi::cal(10, move |res| *result.borrow_mut() = Some(res.unwrap())).timeout(10_000);
+    });
+
+    assert_eq!(*result.borrow(), Some(55));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn cancel_after_the_callback_ran_does_nothing() {
+    let called = Rc::new(RefCell::new(0));
+
+    let called_clone = called.clone();
+    Runtime::new().run(move || {
+        let handle: Rc<RefCell<Option<WorkHandle>>> = Rc::new(RefCell::new(None));
+        let handle_clone = handle.clone();
+        let called = called_clone.clone();
+        let work = Fibonacchi::cal(10, move |res| {
+            res.unwrap();
+            *called.borrow_mut() += 1;
+            let handle = handle_clone.clone();
+            Immediate::set_immediate(move || {
+                let work = handle.borrow_mut().take().unwrap();
+                work.cancel();
+                assert!(!work.is_cancelled());
+            });
+        });
+        *handle.borrow_mut() = Some(work);
+    });
+
+    assert_eq!(*called.borrow(), 1);
+}
@@ -0,0 +1,17 @@
+use async_with_callback::{runtime::Runtime, task::Fs};
+use std::{cell::RefCell, io, rc::Rc};
+
+#[test]
+fn fs_read_reports_missing_file() {
+    let kind = Rc::new(RefCell::new(None));
+
+    let kind_clone = kind.clone();
+    Runtime::new().run(move || {
+        let kind = kind_clone.clone();
+        Fs::read("does/not/exist.txt", move |res| {
+            *kind.borrow_mut() = res.err().map(|e| e.kind());
+        });
+    });
+
+    assert_eq!(*kind.borrow(), Some(io::ErrorKind::NotFound));
+}
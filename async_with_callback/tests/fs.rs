@@ -0,0 +1,104 @@
+use async_with_callback::{runtime::Runtime, task::Fs};
+use std::{cell::RefCell, fs, io, path::Path, rc::Rc};
+
+#[test]
+fn read_bytes_delivers_binary_content() {
+    const PATH: &str = concat!(env!("CARGO_TARGET_TMPDIR"), "/read_bytes.bin");
+    let content = vec![0x89, b'P', b'N', b'G', 0xff, 0x00, 0xfe];
+    fs::write(PATH, &content).unwrap();
+
+    let results = Rc::new(RefCell::new(vec![]));
+    let results_clone = results.clone();
+    Runtime::new().run(move || {
+        let results = results_clone.clone();
+        Fs::read_bytes(PATH, move |res| {
+            results.borrow_mut().push(res.unwrap());
+        });
+        let results = results_clone.clone();
+        Fs::read_bytes("does/not/exist.bin", move |res| {
+            let kind = res.unwrap_err().kind();
+            assert_eq!(kind, io::ErrorKind::NotFound);
+            results.borrow_mut().push(vec![]);
+        });
+    });
+
+    let mut results = results.borrow().clone();
+    results.sort();
+    assert_eq!(results, vec![vec![], content]);
+}
+
+#[test]
+fn file_system_round_trip() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_round_trip");
+    let _ = fs::remove_dir_all(&dir);
+    let steps = Rc::new(RefCell::new(vec![]));
+
+    let (dir_clone, steps_clone) = (dir.clone(), steps.clone());
+    Runtime::new().run(move || {
+        let (dir, steps) = (dir_clone.clone(), steps_clone.clone());
+        Fs::create_dir_all(dir.join("sub"), move |res| {
+            res.unwrap();
+            let (dir, steps) = (dir.clone(), steps.clone());
+            Fs::write(dir.join("a.txt"), "hello", move |res| {
+                res.unwrap();
+                let (dir, steps) = (dir.clone(), steps.clone());
+                Fs::append(dir.join("a.txt"), " world", move |res| {
+                    res.unwrap();
+                    let (dir, steps) = (dir.clone(), steps.clone());
+                    Fs::copy(dir.join("a.txt"), dir.join("b.txt"), move |res| {
+                        assert_eq!(res.unwrap(), 11);
+                        let (dir, steps) = (dir.clone(), steps.clone());
+                        Fs::rename(dir.join("b.txt"), dir.join("c.txt"), move |res| {
+                            res.unwrap();
+                            check_dir(&dir, &steps);
+                        });
+                    });
+                });
+            });
+        });
+    });
+
+    assert_eq!(
+        *steps.borrow(),
+        vec!["read", "read_dir", "metadata", "exists", "removed"]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn check_dir(dir: &Path, steps: &Rc<RefCell<Vec<&'static str>>>) {
+    let s = steps.clone();
+    Fs::read(dir.join("c.txt"), move |res| {
+        assert_eq!(res.unwrap(), "hello world");
+        s.borrow_mut().push("read");
+    });
+
+    let (s, expected) = (steps.clone(), dir.to_path_buf());
+    Fs::read_dir(dir.to_path_buf(), move |res| {
+        let paths = res.unwrap();
+        let names = ["a.txt", "c.txt", "sub"];
+        let expected: Vec<_> = names.iter().map(|n| expected.join(n)).collect();
+        assert_eq!(paths, expected);
+        s.borrow_mut().push("read_dir");
+    });
+
+    let s = steps.clone();
+    Fs::metadata(dir.join("sub"), move |res| {
+        assert!(res.unwrap().is_dir());
+        s.borrow_mut().push("metadata");
+    });
+
+    let (s, dir) = (steps.clone(), dir.to_path_buf());
+    Fs::exists(dir.join("a.txt"), move |res| {
+        assert!(res.unwrap());
+        s.borrow_mut().push("exists");
+        let (s, dir) = (s.clone(), dir.clone());
+        Fs::remove_file(dir.join("a.txt"), move |res| {
+            res.unwrap();
+            let s = s.clone();
+            Fs::exists(dir.join("a.txt"), move |res| {
+                assert!(!res.unwrap());
+                s.borrow_mut().push("removed");
+            });
+        });
+    });
+}
@@ -0,0 +1,83 @@
+use async_with_callback::{
+    handle::RuntimeHandle,
+    runtime::{HandleKind, Runtime},
+    task::{spawn_blocking, Timeout},
+};
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn unreferenced_interval_does_not_keep_the_loop_alive() {
+    let fired = Rc::new(Cell::new(0));
+
+    let start = Instant::now();
+    let fired_clone = fired.clone();
+    Runtime::new().run(move || {
+        let fired = fired_clone.clone();
+        let id = Timeout::set_interval(10, move || fired.set(fired.get() + 1));
+        assert!(Timeout::unref(id));
+        assert!(!Timeout::has_ref(id));
+        // Keeps the loop running for a while, so the interval fires
+        Timeout::set_timeout(55, || ());
+    });
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(fired.get() >= 3, "{}", fired.get());
+}
+
+#[test]
+fn referenced_again_keeps_the_loop_alive() {
+    let fired = Rc::new(Cell::new(0));
+
+    let fired_clone = fired.clone();
+    Runtime::new().run(move || {
+        let fired = fired_clone.clone();
+        let id = Rc::new(Cell::new(None));
+        let id_clone = id.clone();
+        let timer = Timeout::set_interval(1, move || {
+            fired.set(fired.get() + 1);
+            if fired.get() == 3 {
+                Timeout::clear_interval(id_clone.get().unwrap());
+            }
+        });
+        id.set(Some(timer));
+        Timeout::unref(timer);
+        assert!(Timeout::ref_(timer));
+        assert!(Timeout::has_ref(timer));
+    });
+
+    assert_eq!(fired.get(), 3);
+}
+
+#[test]
+fn unreferenced_sender_does_not_keep_the_loop_alive() {
+    let runtime = Runtime::new();
+    let sender = runtime.loop_sender();
+    sender.unref();
+    assert!(!sender.has_ref());
+
+    let report = runtime.run(|| ());
+    assert!(report.is_success());
+    drop(sender);
+}
+
+#[test]
+fn active_handles_per_kind() {
+    Runtime::new().run(|| {
+        let rt = RuntimeHandle::current();
+        let timer = Timeout::set_timeout(1000, || ());
+        spawn_blocking("work", || (), |_| ());
+        assert_eq!(rt.active_handles(HandleKind::Timer), 1);
+        assert_eq!(rt.active_handles(HandleKind::Work), 1);
+
+        Timeout::unref(timer);
+        assert_eq!(rt.active_handles(HandleKind::Timer), 0);
+        let sender = rt.loop_sender().unwrap();
+        assert_eq!(rt.active_handles(HandleKind::Sender), 1);
+        drop(sender);
+        assert_eq!(rt.active_handles(HandleKind::Sender), 0);
+    });
+}
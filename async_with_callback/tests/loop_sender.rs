@@ -0,0 +1,84 @@
+use async_with_callback::{
+    handle::{LoopClosedError, RuntimeHandle},
+    runtime::Runtime,
+    task::Timeout,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn callbacks_run_on_the_loop_thread_in_order() {
+    let results = Arc::new(Mutex::new(vec![]));
+    let loop_thread = thread::current().id();
+
+    let runtime = Runtime::new();
+    let sender = runtime.loop_sender();
+    let results_clone = results.clone();
+    let library = thread::spawn(move || {
+        for i in 0..3 {
+            thread::sleep(Duration::from_millis(20));
+            let results = results_clone.clone();
+            sender
+                .send(move || {
+                    assert_eq!(thread::current().id(), loop_thread);
+                    results.lock().unwrap().push(i);
+                })
+                .unwrap();
+        }
+    });
+
+    let start = Instant::now();
+    runtime.run(|| ());
+
+    // The loop waited for the sender to be dropped
+    assert!(start.elapsed() >= Duration::from_millis(60));
+    assert_eq!(*results.lock().unwrap(), vec![0, 1, 2]);
+    library.join().unwrap();
+}
+
+#[test]
+fn posted_callbacks_wake_the_loop_and_can_use_the_runtime() {
+    let results = Arc::new(Mutex::new(vec![]));
+
+    let start = Instant::now();
+    let results_clone = results.clone();
+    Runtime::new().run(move || {
+        let sender = RuntimeHandle::current().loop_sender().unwrap();
+        let results = results_clone.clone();
+        // The loop is blocked waiting for this timer when the callback arrives
+        Timeout::set_timeout(300, || ());
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender
+                .send(move || {
+                    results.lock().unwrap().push(("posted", start.elapsed()));
+                    Timeout::set_timeout(10, move || {
+                        results.lock().unwrap().push(("timeout", start.elapsed()));
+                    });
+                })
+                .unwrap();
+        });
+    });
+
+    let results = results.lock().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, "posted");
+    assert!(
+        results[0].1 < Duration::from_millis(200),
+        "{:?}",
+        results[0].1
+    );
+    assert_eq!(results[1].0, "timeout");
+}
+
+#[test]
+fn send_fails_after_the_runtime_finished() {
+    let runtime = Runtime::new();
+    let sender = runtime.loop_sender();
+    drop(runtime);
+
+    assert_eq!(sender.send(|| ()), Err(LoopClosedError));
+}
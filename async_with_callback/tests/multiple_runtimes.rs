@@ -0,0 +1,106 @@
+use async_with_callback::{
+    runtime::Runtime,
+    task::{spawn_blocking, Timeout},
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Barrier},
+    thread,
+    time::Duration,
+};
+
+/// Runs a runtime with its own worker names and returns the names of the
+/// workers its tasks ran on, and whether every callback ran on the loop thread.
+fn run_tenant(name: &'static str, both_running: Arc<Barrier>) -> (Vec<String>, bool) {
+    let workers = Rc::new(RefCell::new(vec![]));
+    let on_loop = Rc::new(RefCell::new(true));
+    let loop_thread = thread::current().id();
+
+    let runtime = Runtime::builder()
+        .worker_threads(2)
+        .thread_name(name)
+        .build();
+    let (workers_clone, on_loop_clone) = (workers.clone(), on_loop.clone());
+    runtime.run(move || {
+        // Both loops are running from here on
+        both_running.wait();
+        for _ in 0..4 {
+            let workers = workers_clone.clone();
+            let on_loop = on_loop_clone.clone();
+            spawn_blocking(
+                "name",
+                || {
+                    thread::sleep(Duration::from_millis(10));
+                    thread::current().name().unwrap().to_string()
+                },
+                move |res| {
+                    *on_loop.borrow_mut() &= thread::current().id() == loop_thread;
+                    workers.borrow_mut().push(res.unwrap());
+                },
+            );
+        }
+        let on_loop = on_loop_clone.clone();
+        Timeout::set_timeout(20, move || {
+            *on_loop.borrow_mut() &= thread::current().id() == loop_thread;
+        });
+    });
+
+    let workers = workers.borrow().clone();
+    let on_loop = *on_loop.borrow();
+    (workers, on_loop)
+}
+
+#[test]
+fn runtimes_on_different_threads_are_isolated() {
+    let both_running = Arc::new(Barrier::new(2));
+
+    let tenants: Vec<_> = ["tenant-a", "tenant-b"]
+        .iter()
+        .map(|&name| {
+            let both_running = both_running.clone();
+            thread::spawn(move || (name, run_tenant(name, both_running)))
+        })
+        .collect();
+
+    for tenant in tenants {
+        let (name, (workers, on_loop)) = tenant.join().unwrap();
+        assert!(on_loop);
+        assert_eq!(workers.len(), 4);
+        for worker in workers {
+            assert!(worker.starts_with(name), "{} ran on {}", name, worker);
+        }
+    }
+}
+
+#[test]
+fn nested_runtime_keeps_its_timers_apart() {
+    let fired = Rc::new(RefCell::new(vec![]));
+
+    let fired_clone = fired.clone();
+    let outer = Runtime::new();
+    let outer_id = outer.id();
+    outer.run(move || {
+        let fired = fired_clone.clone();
+        let outer_timer = Timeout::set_timeout(10, {
+            let fired = fired.clone();
+            move || fired.borrow_mut().push("outer")
+        });
+
+        let inner = Runtime::new();
+        assert_ne!(inner.id(), outer_id);
+        let fired_inner = fired.clone();
+        inner.run(move || {
+            let fired = fired_inner.clone();
+            let inner_timer = Timeout::set_timeout(10, move || fired.borrow_mut().push("inner"));
+            assert_eq!(inner_timer.as_u64(), outer_timer.as_u64());
+            // The inner runtime doesn't know the timer of the outer one
+            assert!(!Timeout::clear_timeout(outer_timer));
+        });
+
+        // Back in the outer runtime
+        assert!(Timeout::has_ref(outer_timer));
+    });
+
+    assert_eq!(*fired.borrow(), vec!["inner", "outer"]);
+}
@@ -0,0 +1,180 @@
+use async_with_callback::{
+    runtime::{RunError, Runtime},
+    task::{spawn_blocking, Fibonacchi, TaskError, Timeout},
+};
+use std::{
+    any::Any,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+#[test]
+fn panics_go_to_the_hook_and_the_loop_keeps_running() {
+    let panics = Rc::new(RefCell::new(vec![]));
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let panics_clone = panics.clone();
+    let results_clone = results.clone();
+    Runtime::builder()
+        .worker_threads(1)
+        .on_uncaught_panic(move |payload| panics_clone.borrow_mut().push(message(&*payload)))
+        .build()
+        .run(move || {
+            spawn_blocking(
+                "boom",
+                || panic!("task panicked"),
+                |_: Result<(), TaskError>| {
+                    unreachable!("the callback of a panicked task never runs")
+                },
+            );
+
+            // The only worker survived the panic
+            let results = results_clone.clone();
+            Fibonacchi::cal(10, move |res| results.borrow_mut().push(res.unwrap()));
+
+            Timeout::set_timeout(0, || panic!("callback panicked"));
+            let results = results_clone.clone();
+            Timeout::set_timeout(200, move || results.borrow_mut().push(0));
+        });
+
+    let mut panics = panics.borrow().clone();
+    panics.sort();
+    assert_eq!(panics, vec!["callback panicked", "task panicked"]);
+    assert_eq!(*results.borrow(), vec![55, 0]);
+}
+
+#[test]
+fn a_panicked_task_drops_its_deadline() {
+    let panics = Rc::new(RefCell::new(vec![]));
+    let start = Instant::now();
+
+    let panics_clone = panics.clone();
+    Runtime::builder()
+        .worker_threads(1)
+        .on_uncaught_panic(move |payload| panics_clone.borrow_mut().push(message(&*payload)))
+        .build()
+        .run(|| {
+            let handle = spawn_blocking(
+                "boom",
+                || panic!("task panicked"),
+                |_: Result<(), TaskError>| {
+                    unreachable!("the callback of a panicked task never runs")
+                },
+            )
+            .timeout(10_000);
+
+            // Cancelling after the panic doesn't call the callback either. The
+            // panic hook may print a backtrace first, so leave it some time
+            Timeout::set_timeout(200, move || {
+                handle.cancel();
+                assert!(!handle.is_cancelled());
+            });
+        });
+
+    // The deadline didn't keep the loop alive
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(*panics.borrow(), vec!["task panicked"]);
+}
+
+#[test]
+fn panics_unwind_out_of_run_without_a_hook() {
+    let runtime = Runtime::builder().worker_threads(1).build();
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        runtime.run(|| {
+            spawn_blocking("boom", || panic!("task panicked"), |_: Result<(), _>| ());
+        });
+    }));
+    assert_eq!(message(&*res.unwrap_err()), "task panicked");
+}
+
+#[test]
+fn dead_workers_are_respawned() {
+    let starts = Arc::new(AtomicUsize::new(0));
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let starts_clone = starts.clone();
+    let results_clone = results.clone();
+    Runtime::builder()
+        .worker_threads(1)
+        .on_thread_start(move |_| {
+            if starts_clone.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first start fails");
+            }
+        })
+        .build()
+        .run(move || {
+            for n in [5, 10] {
+                let results = results_clone.clone();
+                Fibonacchi::cal(n, move |res| results.borrow_mut().push(res.unwrap()));
+            }
+        });
+
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+    assert_eq!(*results.borrow(), vec![5, 55]);
+}
+
+#[test]
+fn work_sent_to_a_worker_which_died_on_start_still_runs() {
+    let starts = Arc::new(AtomicUsize::new(0));
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let starts_clone = starts.clone();
+    let results_clone = results.clone();
+    Runtime::builder()
+        .worker_threads(1)
+        .on_thread_start(move |_| {
+            if starts_clone.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first start fails");
+            }
+        })
+        .build()
+        .run(move || {
+            // The worker is dead before the loop hands it the work
+            std::thread::sleep(Duration::from_millis(50));
+            let results = results_clone.clone();
+            Fibonacchi::cal(10, move |res| results.borrow_mut().push(res.unwrap()));
+        });
+
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+    assert_eq!(*results.borrow(), vec![55]);
+}
+
+#[test]
+fn workers_which_never_start_are_given_up() {
+    let starts = Arc::new(AtomicUsize::new(0));
+    let result = Rc::new(RefCell::new(None));
+
+    let starts_clone = starts.clone();
+    let result_clone = result.clone();
+    let report = Runtime::builder()
+        .worker_threads(1)
+        .on_thread_start(move |_| {
+            starts_clone.fetch_add(1, Ordering::SeqCst);
+            panic!("start always fails");
+        })
+        .build()
+        .run(move || {
+            let result = result_clone.clone();
+            Fibonacchi::cal(10, move |res| *result.borrow_mut() = Some(res));
+        });
+
+    assert_eq!(starts.load(Ordering::SeqCst), 3);
+    assert_eq!(report.errors, vec![RunError::WorkerStartFailed(0)]);
+    let e = result.borrow_mut().take().unwrap().unwrap_err();
+    let e = e.get_ref().and_then(|e| e.downcast_ref::<TaskError>());
+    assert_eq!(e, Some(&TaskError::NoWorkers));
+}
@@ -0,0 +1,92 @@
+use async_with_callback::{
+    runtime::Runtime,
+    task::{Fibonacchi, Immediate, Process, Timeout},
+};
+use std::{cell::RefCell, rc::Rc};
+
+type Log = Rc<RefCell<Vec<&'static str>>>;
+
+fn run_logged(f: impl Fn(Log) + 'static) -> Vec<&'static str> {
+    let log: Log = Rc::new(RefCell::new(vec![]));
+    let log_clone = log.clone();
+    Runtime::builder()
+        .worker_threads(1)
+        .build()
+        .run(move || f(log_clone.clone()));
+    let entries = log.borrow().clone();
+    entries
+}
+
+fn push(log: &Log, entry: &'static str) -> impl Fn() + 'static {
+    let log = log.clone();
+    move || log.borrow_mut().push(entry)
+}
+
+#[test]
+fn next_tick_runs_before_the_loop_starts() {
+    let order = run_logged(|log| {
+        let timeout = push(&log, "timeout");
+        Timeout::set_timeout(0, timeout);
+        Immediate::set_immediate(push(&log, "immediate"));
+        Process::next_tick(push(&log, "next tick"));
+        log.borrow_mut().push("main");
+    });
+
+    assert_eq!(order, vec!["main", "next tick", "timeout", "immediate"]);
+}
+
+#[test]
+fn immediate_runs_before_timeout_after_io() {
+    let order = run_logged(|log| {
+        let log = log.clone();
+        Fibonacchi::cal(10, move |_| {
+            let timeout = push(&log, "timeout");
+            Timeout::set_timeout(0, timeout);
+            Immediate::set_immediate(push(&log, "immediate"));
+            log.borrow_mut().push("io");
+        });
+    });
+
+    assert_eq!(order, vec!["io", "immediate", "timeout"]);
+}
+
+#[test]
+fn next_tick_runs_between_callbacks() {
+    let order = run_logged(|log| {
+        let first = log.clone();
+        Timeout::set_timeout(0, move || {
+            first.borrow_mut().push("first");
+            let tick = first.clone();
+            Process::next_tick(move || {
+                tick.borrow_mut().push("tick");
+                Process::next_tick(push(&tick, "nested tick"));
+            });
+        });
+        let second = push(&log, "second");
+        Timeout::set_timeout(0, second);
+    });
+
+    assert_eq!(order, vec!["first", "tick", "nested tick", "second"]);
+}
+
+#[test]
+fn immediate_queued_in_check_phase_runs_next_iteration() {
+    let order = run_logged(|log| {
+        let outer = log.clone();
+        Immediate::set_immediate(move || {
+            outer.borrow_mut().push("immediate 1");
+            Immediate::set_immediate(push(&outer, "immediate 3"));
+        });
+        let timer_log = log.clone();
+        Immediate::set_immediate(move || {
+            timer_log.borrow_mut().push("immediate 2");
+            let timeout = push(&timer_log, "timeout");
+            Timeout::set_timeout(0, timeout);
+        });
+    });
+
+    assert_eq!(
+        order,
+        vec!["immediate 1", "immediate 2", "timeout", "immediate 3"]
+    );
+}
@@ -0,0 +1,68 @@
+use async_with_callback::{
+    runtime::{RunError, Runtime},
+    task::{spawn_blocking, Immediate, Process, Timeout},
+};
+use std::{thread, time::Duration};
+
+#[test]
+fn report_counts_ticks_and_callbacks() {
+    let report = Runtime::new().run(|| {
+        Timeout::set_timeout(0, || Process::next_tick(|| ()));
+        Immediate::set_immediate(|| ());
+        spawn_blocking("work", || (), |res| res.unwrap());
+    });
+
+    assert!(report.ticks >= 1);
+    assert_eq!(report.callbacks, 4);
+    assert_eq!(report.exit_code, 0);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert!(report.is_success());
+}
+
+#[test]
+fn exit_code_set_by_a_callback() {
+    let report = Runtime::new().run(|| {
+        Timeout::set_timeout(0, || {
+            assert_eq!(Process::exit_code(), 0);
+            Process::set_exit_code(3);
+        });
+    });
+
+    assert_eq!(report.exit_code, 3);
+    assert!(!report.is_success());
+}
+
+#[test]
+fn panics_passed_to_the_hook_are_reported() {
+    let report = Runtime::builder()
+        .on_uncaught_panic(|_| ())
+        .build()
+        .run(|| {
+            Immediate::set_immediate(|| panic!("boom"));
+            spawn_blocking("work", || panic!("boom {}", 2), |_: Result<(), _>| ());
+        });
+
+    // The panics happen on different threads, so their order isn't fixed
+    let mut errors = report.errors;
+    errors.sort_by_key(|e| e.to_string());
+    assert_eq!(
+        errors,
+        vec![
+            RunError::UncaughtPanic("boom".to_string()),
+            RunError::UncaughtPanic("boom 2".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn abandoned_work_is_reported() {
+    let report = Runtime::builder()
+        .shutdown_timeout(Duration::from_millis(20))
+        .build()
+        .run(|| {
+            spawn_blocking("slow", || thread::sleep(Duration::from_millis(300)), |_| ());
+            Timeout::set_timeout(10, Runtime::shutdown);
+        });
+
+    assert_eq!(report.errors, vec![RunError::ShutdownTimedOut(1)]);
+}
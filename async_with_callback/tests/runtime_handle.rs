@@ -0,0 +1,44 @@
+use async_with_callback::{
+    handle::{NoRuntimeError, RuntimeHandle},
+    runtime::Runtime,
+    task::{Fibonacchi, Timeout},
+};
+use std::{cell::RefCell, panic, rc::Rc};
+
+#[test]
+fn no_runtime_outside_run() {
+    assert!(RuntimeHandle::try_current().is_err());
+
+    let err = panic::catch_unwind(|| Timeout::set_timeout(10, || {})).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert_eq!(msg, &NoRuntimeError.to_string());
+}
+
+#[test]
+fn handle_is_invalid_after_run() {
+    let saved = Rc::new(RefCell::new(None));
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let rt = Runtime::new();
+    let saved_clone = saved.clone();
+    let results_clone = results.clone();
+    rt.run(move || {
+        let handle = RuntimeHandle::try_current().expect("inside run");
+        assert!(handle.is_running());
+        *saved_clone.borrow_mut() = Some(handle);
+
+        let results = results_clone.clone();
+        Fibonacchi::cal(10, move |res| {
+            // Callbacks can start new tasks on the same runtime
+            let results_inner = results.clone();
+            results.borrow_mut().push(res.unwrap());
+            Fibonacchi::cal(5, move |res| {
+                results_inner.borrow_mut().push(res.unwrap());
+            });
+        });
+    });
+
+    assert_eq!(*results.borrow(), vec![55, 5]);
+    assert!(!saved.borrow().as_ref().unwrap().is_running());
+    assert!(RuntimeHandle::try_current().is_err());
+}
@@ -0,0 +1,125 @@
+use async_with_callback::{
+    builder::RuntimeBuilder,
+    runtime::Runtime,
+    scheduler::{SchedulingPolicy, ThreadPoolMode},
+    task::{spawn_blocking, Fibonacchi, Fs},
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+fn callback_order(policy: SchedulingPolicy) -> Vec<usize> {
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let runtime = Runtime::builder()
+        .worker_threads(1)
+        .scheduling_policy(policy)
+        .build();
+
+    let results_clone = results.clone();
+    runtime.run(move || {
+        for n in &[5, 10, 15, 20] {
+            let results = results_clone.clone();
+            Fibonacchi::cal(*n, move |res| {
+                results.borrow_mut().push(res.unwrap());
+            });
+        }
+    });
+
+    let order = results.borrow().clone();
+    order
+}
+
+#[test]
+fn fifo_by_default() {
+    assert_eq!(
+        callback_order(SchedulingPolicy::default()),
+        vec![5, 55, 610, 6765]
+    );
+}
+
+#[test]
+fn lifo_policy() {
+    assert_eq!(
+        callback_order(SchedulingPolicy::Lifo),
+        vec![6765, 610, 55, 5]
+    );
+}
+
+/// Fills every worker with slow CPU bound work, then reads a file. Returns how
+/// long the read took to call back.
+fn read_behind_cpu_work(builder: RuntimeBuilder) -> Duration {
+    let elapsed = Rc::new(RefCell::new(None));
+
+    let elapsed_clone = elapsed.clone();
+    builder.worker_threads(2).build().run(move || {
+        for _ in 0..4 {
+            spawn_blocking("slow", || thread::sleep(Duration::from_millis(300)), |_| ());
+        }
+
+        let start = Instant::now();
+        let elapsed = elapsed_clone.clone();
+        Fs::read("Cargo.toml", move |res| {
+            res.unwrap();
+            *elapsed.borrow_mut() = Some(start.elapsed());
+        });
+    });
+
+    let elapsed = elapsed.borrow().unwrap();
+    elapsed
+}
+
+#[test]
+fn reserved_io_workers_prevent_starvation() {
+    // Without a reserved worker the read waits for the CPU bound work
+    let starved = read_behind_cpu_work(Runtime::builder());
+    assert!(starved >= Duration::from_millis(300));
+
+    let reserved = read_behind_cpu_work(Runtime::builder().reserved_io_workers(1));
+    assert!(reserved < Duration::from_millis(150), "{:?}", reserved);
+
+    let stealing = read_behind_cpu_work(
+        Runtime::builder()
+            .thread_pool_mode(ThreadPoolMode::WorkStealing)
+            .reserved_io_workers(1),
+    );
+    assert!(stealing < Duration::from_millis(150), "{:?}", stealing);
+}
+
+#[test]
+fn stealing_workers_start_work_without_the_loop() {
+    let started = Arc::new(AtomicBool::new(false));
+    let called = Rc::new(RefCell::new(false));
+
+    let runtime = Runtime::builder()
+        .worker_threads(1)
+        .thread_pool_mode(ThreadPoolMode::WorkStealing)
+        .build();
+
+    let (started_clone, called_clone) = (started.clone(), called.clone());
+    runtime.run(move || {
+        let started = started_clone.clone();
+        let called = called_clone.clone();
+        spawn_blocking(
+            "flag",
+            move || started.store(true, Ordering::SeqCst),
+            move |res| *called.borrow_mut() = res.is_ok(),
+        );
+
+        // The loop doesn't get to dispatch anything while this callback runs
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !started_clone.load(Ordering::SeqCst) && Instant::now() < deadline {
+            thread::yield_now();
+        }
+        assert!(started_clone.load(Ordering::SeqCst));
+    });
+
+    assert!(*called.borrow());
+}
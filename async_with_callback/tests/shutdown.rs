@@ -0,0 +1,130 @@
+use async_with_callback::{
+    runtime::Runtime,
+    task::{spawn_blocking, TaskError, Timeout},
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn shutdown_cancels_queued_work_and_timers() {
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let runtime = Runtime::builder().worker_threads(1).build();
+    let start = Instant::now();
+    let results_clone = results.clone();
+    runtime.run(move || {
+        let results = results_clone.clone();
+        spawn_blocking(
+            "running",
+            || thread::sleep(Duration::from_millis(100)),
+            move |res| results.borrow_mut().push(("running", res)),
+        );
+        let results = results_clone.clone();
+        spawn_blocking(
+            "queued",
+            || (),
+            move |res| results.borrow_mut().push(("queued", res)),
+        );
+
+        let results = results_clone.clone();
+        Timeout::set_interval(1000, move || {
+            results.borrow_mut().push(("interval", Ok(())))
+        });
+        Timeout::set_timeout(20, Runtime::shutdown);
+    });
+
+    // The loop waited for the running work, but not for the interval
+    assert!(start.elapsed() < Duration::from_millis(900));
+    assert_eq!(
+        *results.borrow(),
+        vec![("queued", Err(TaskError::Cancelled)), ("running", Ok(()))]
+    );
+}
+
+#[test]
+fn work_submitted_while_shutting_down_is_cancelled() {
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let results_clone = results.clone();
+    Runtime::new().run(move || {
+        Runtime::shutdown();
+
+        let results = results_clone.clone();
+        spawn_blocking("late", || (), move |res| results.borrow_mut().push(res));
+        let results = results_clone.clone();
+        Timeout::set_timeout(0, move || results.borrow_mut().push(Ok(())));
+    });
+
+    assert_eq!(*results.borrow(), vec![Err(TaskError::Cancelled)]);
+}
+
+#[test]
+fn shutdown_timeout_abandons_running_work() {
+    let called = Rc::new(RefCell::new(false));
+
+    let runtime = Runtime::builder()
+        .shutdown_timeout(Duration::from_millis(50))
+        .build();
+    let start = Instant::now();
+    let called_clone = called.clone();
+    runtime.run(move || {
+        let called = called_clone.clone();
+        spawn_blocking(
+            "slow",
+            || thread::sleep(Duration::from_millis(500)),
+            move |_| *called.borrow_mut() = true,
+        );
+        Timeout::set_timeout(20, Runtime::shutdown);
+    });
+
+    assert!(start.elapsed() < Duration::from_millis(400));
+    assert!(!*called.borrow());
+}
+
+#[test]
+fn abandoned_work_finishes_after_the_loop_is_gone() {
+    let (stopped, worker_stopped) = mpsc::channel();
+    let stopped = Mutex::new(stopped);
+
+    let runtime = Runtime::builder()
+        .worker_threads(1)
+        .shutdown_timeout(Duration::from_millis(20))
+        .on_thread_stop(move |id| stopped.lock().unwrap().send(id).unwrap())
+        .build();
+    runtime.run(|| {
+        spawn_blocking(
+            "slow",
+            || thread::sleep(Duration::from_millis(100)),
+            |_| unreachable!("the loop is gone"),
+        );
+        Timeout::set_timeout(10, Runtime::shutdown);
+    });
+
+    // The worker only gets to its stop hook if delivering the late result
+    // to the closed loop didn't panic
+    let id = worker_stopped.recv_timeout(Duration::from_secs(5));
+    assert_eq!(id, Ok(0));
+}
+
+#[test]
+fn shutdown_posted_from_another_thread() {
+    let runtime = Runtime::new();
+    let sender = runtime.loop_sender();
+    let keep_alive = sender.clone();
+
+    let start = Instant::now();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        sender.send(Runtime::shutdown).unwrap();
+    });
+    // The loop doesn't wait for `keep_alive` after the shutdown
+    runtime.run(|| ());
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+    drop(keep_alive);
+}
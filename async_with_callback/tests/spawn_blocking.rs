@@ -0,0 +1,51 @@
+use async_with_callback::{runtime::Runtime, task::spawn_blocking};
+use std::{cell::RefCell, rc::Rc, thread};
+
+#[derive(Debug, PartialEq)]
+struct Histogram {
+    thread: String,
+    buckets: Vec<usize>,
+}
+
+#[test]
+fn spawn_blocking_delivers_typed_results() {
+    let results = Rc::new(RefCell::new(vec![]));
+
+    let results_clone = results.clone();
+    Runtime::builder()
+        .thread_name("blocking")
+        .build()
+        .run(move || {
+            let data = vec![1, 5, 5, 9, 3];
+            let results = results_clone.clone();
+            spawn_blocking(
+                "histogram",
+                move || {
+                    let mut buckets = vec![0; 10];
+                    data.into_iter().for_each(|n| buckets[n] += 1);
+                    let thread = thread::current().name().unwrap().to_string();
+                    Histogram { thread, buckets }
+                },
+                move |histogram| {
+                    let histogram: Histogram = histogram.unwrap();
+                    assert!(histogram.thread.starts_with("blocking-"));
+                    results.borrow_mut().push(histogram.buckets);
+
+                    // Work can be chained from a callback with another type
+                    let results = results.clone();
+                    spawn_blocking(
+                        "sum",
+                        || 42usize,
+                        move |sum| {
+                            results.borrow_mut().push(vec![sum.unwrap()]);
+                        },
+                    );
+                },
+            );
+        });
+
+    assert_eq!(
+        *results.borrow(),
+        vec![vec![0, 1, 0, 1, 0, 2, 0, 0, 0, 1], vec![42]]
+    );
+}
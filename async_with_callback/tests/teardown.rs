@@ -0,0 +1,63 @@
+//! The only test of this binary, so no other test starts threads while it
+//! counts them.
+use async_with_callback::{runtime::Runtime, scheduler::ThreadPoolMode, task::Timeout};
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+fn thread_count() -> usize {
+    fs::read_dir("/proc/self/task").unwrap().count()
+}
+
+/// Waits until the threads the runtime started are gone again.
+fn wait_for_threads(expected: usize) {
+    let start = Instant::now();
+    while thread_count() != expected {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{} threads instead of {}",
+            thread_count(),
+            expected
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn runtimes_stop_their_threads_on_every_path() {
+    let before = thread_count();
+
+    // Built but never run
+    let (stopped, stops) = mpsc::channel();
+    let runtime = Runtime::builder()
+        .worker_threads(2)
+        .on_thread_stop(move |id| stopped.send(id).unwrap())
+        .build();
+    assert_eq!(thread_count(), before + 3);
+    drop(runtime);
+    // The workers were joined
+    assert_eq!(stops.try_iter().count(), 2);
+    wait_for_threads(before);
+
+    // Unwound out of `run`, with workers waiting on their queues
+    let (stopped, stops) = mpsc::channel();
+    let runtime = Runtime::builder()
+        .worker_threads(2)
+        .thread_pool_mode(ThreadPoolMode::WorkStealing)
+        .on_thread_stop(move |id| stopped.send(id).unwrap())
+        .build();
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        runtime.run(|| {
+            Timeout::set_timeout(10, || panic!("boom"));
+        })
+    }));
+    assert!(res.is_err());
+    for _ in 0..2 {
+        stops.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    wait_for_threads(before);
+}
@@ -0,0 +1,105 @@
+use async_with_callback::{runtime::Runtime, task::Timeout};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn timeout_fires_on_time_without_other_events() {
+    let fired_after = Rc::new(RefCell::new(None));
+
+    let start = Instant::now();
+    let fired_clone = fired_after.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        let fired = fired_clone.clone();
+        Timeout::set_timeout(50, move || {
+            *fired.borrow_mut() = Some(start.elapsed());
+        });
+    });
+
+    let latency = fired_after.borrow().expect("timer never fired");
+    assert!(latency >= Duration::from_millis(50), "{:?}", latency);
+    assert!(latency < Duration::from_millis(100), "{:?}", latency);
+}
+
+#[test]
+fn timeouts_fire_in_deadline_order() {
+    let fired = Rc::new(RefCell::new(vec![]));
+
+    let fired_clone = fired.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        let fired = fired_clone.clone();
+        Timeout::set_timeout(30, move || fired.borrow_mut().push(30));
+        let fired = fired_clone.clone();
+        Timeout::set_timeout(10, move || fired.borrow_mut().push(10));
+    });
+
+    assert_eq!(*fired.borrow(), vec![10, 30]);
+}
+
+#[test]
+fn timeouts_with_same_delay_run_in_insertion_order() {
+    let fired = Rc::new(RefCell::new(vec![]));
+
+    let fired_clone = fired.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        for i in 0..5 {
+            let fired = fired_clone.clone();
+            Timeout::set_timeout(20, move || fired.borrow_mut().push(i));
+        }
+    });
+
+    assert_eq!(*fired.borrow(), vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn cleared_timeout_never_fires_and_loop_exits() {
+    let fired = Rc::new(RefCell::new(vec![]));
+
+    let start = Instant::now();
+    let fired_clone = fired.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        let fired = fired_clone.clone();
+        let id = Timeout::set_timeout(1000, move || fired.borrow_mut().push("cleared"));
+        assert!(Timeout::clear_timeout(id));
+        assert!(!Timeout::clear_timeout(id));
+
+        // Clearing a timer which expired in the same tick still prevents it
+        let second = Rc::new(RefCell::new(None));
+        let fired = fired_clone.clone();
+        let second_clone = second.clone();
+        Timeout::set_timeout(0, move || {
+            fired.borrow_mut().push("first");
+            assert!(Timeout::clear_timeout(second_clone.borrow().unwrap()));
+        });
+        let fired = fired_clone.clone();
+        let id = Timeout::set_timeout(0, move || fired.borrow_mut().push("second"));
+        *second.borrow_mut() = Some(id);
+    });
+
+    assert_eq!(*fired.borrow(), vec!["first"]);
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn interval_repeats_until_cleared() {
+    let count = Rc::new(RefCell::new(0));
+    let id = Rc::new(RefCell::new(None));
+
+    let count_clone = count.clone();
+    Runtime::builder().worker_threads(1).build().run(move || {
+        let count = count_clone.clone();
+        let id_clone = id.clone();
+        let interval = Timeout::set_interval(5, move || {
+            *count.borrow_mut() += 1;
+            if *count.borrow() == 3 {
+                let id = id_clone.borrow().unwrap();
+                assert!(Timeout::clear_interval(id));
+            }
+        });
+        *id.borrow_mut() = Some(interval);
+    });
+
+    assert_eq!(*count.borrow(), 3);
+}
@@ -0,0 +1,82 @@
+//! Compares the throughput of the two thread pool modes with tiny tasks, where
+//! the cost of getting work to a worker dominates.
+//!
+//! ```text
+//! cargo run --release --bin thread_pool_bench -- [tasks] [workers]
+//! ```
+use async_with_callback::{runtime::Runtime, scheduler::ThreadPoolMode, task::spawn_blocking};
+use std::{
+    cell::Cell,
+    env,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Submits every task at once from the first callback.
+fn burst(tasks: usize) {
+    for i in 0..tasks {
+        spawn_blocking(
+            "bench",
+            move || i.wrapping_mul(i),
+            |res| {
+                res.unwrap();
+            },
+        );
+    }
+}
+
+/// Every callback submits the next task, so only one task is in flight.
+fn chain(left: Rc<Cell<usize>>) {
+    if left.get() == 0 {
+        return;
+    }
+    left.set(left.get() - 1);
+    spawn_blocking(
+        "bench",
+        || (),
+        move |res| {
+            res.unwrap();
+            chain(left);
+        },
+    );
+}
+
+fn measure(mode: ThreadPoolMode, workers: usize, run: impl Fn() + 'static) -> Duration {
+    let runtime = Runtime::builder()
+        .worker_threads(workers)
+        .thread_pool_mode(mode)
+        .build();
+
+    let start = Instant::now();
+    runtime.run(run);
+    start.elapsed()
+}
+
+fn report(name: &str, mode: ThreadPoolMode, tasks: usize, elapsed: Duration) {
+    println!(
+        "{:<6} {:<12} {:>8} tasks in {:>8.1?} {:>12.0} tasks/s",
+        name,
+        format!("{:?}", mode),
+        tasks,
+        elapsed,
+        tasks as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let tasks = args.next().and_then(|n| n.parse().ok()).unwrap_or(100_000);
+    let workers = args
+        .next()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| Runtime::builder().worker_count());
+
+    println!("{} workers", workers);
+    for mode in [ThreadPoolMode::Dispatcher, ThreadPoolMode::WorkStealing] {
+        let elapsed = measure(mode, workers, move || burst(tasks));
+        report("burst", mode, tasks, elapsed);
+
+        let elapsed = measure(mode, workers, move || chain(Rc::new(Cell::new(tasks))));
+        report("chain", mode, tasks, elapsed);
+    }
+}
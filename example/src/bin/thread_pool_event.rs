@@ -1,4 +1,5 @@
 use async_with_callback::{
+    logger,
     runtime::Runtime,
     task::{Fibonacchi, Fs, Http},
 };
@@ -7,31 +8,36 @@ fn thread_pool_event() {
     Fs::read(
         "./README.md",
         |result| {
-            println!("{}", result.into_string().unwrap());
+            match result {
+                Ok(content) => println!("{}", content),
+                Err(e) => println!("failed to read README.md: {}", e),
+            }
         },
     );
 
     Fibonacchi::cal(5, |result| {
-        println!("{}", result.into_int().unwrap());
+        println!("{}", result.unwrap());
     });
 
     Fibonacchi::cal(10, |result| {
-        println!("{}", result.into_int().unwrap());
+        println!("{}", result.unwrap());
     });
 
     Fibonacchi::cal(15, |result| {
-        println!("{}", result.into_int().unwrap());
+        println!("{}", result.unwrap());
     });
 
     Fibonacchi::cal(20, |result| {
-        println!("{}", result.into_int().unwrap());
+        println!("{}", result.unwrap());
     });
 
     // `http_get_slow` let's us define a latency we want to simulate
     println!("Registering http get request to google.com");
     Http::http_get_slow("http//www.baidu.com", 500, |result| {
-        let result = result.into_string().unwrap();
-        print_content(result.trim());
+        match result {
+            Ok(result) => print_content(result.trim()),
+            Err(e) => println!("request failed: {}", e),
+        }
     });
 }
 
@@ -45,7 +51,7 @@ fn print_content(t: impl std::fmt::Display) {
     let opt_location = opt_location.map(|loc| {
         content[loc..]
         .lines()
-        .nth(0)
+        .next()
         .map(|l| format!("{}\n",l))
         .unwrap_or(String::new())
     });
@@ -60,6 +66,6 @@ fn print_content(t: impl std::fmt::Display) {
 }
 
 fn main() {
-    let runtime = Runtime::new();
+    let runtime = Runtime::builder().logger(logger::from_env()).build();
     runtime.run(thread_pool_event);
 }
@@ -1,10 +1,11 @@
+use async_with_callback::logger::{self, Level, Logger, Record};
 use std::{
+    collections::HashMap,
     future::Future,
+    io::{self, Write},
+    net::TcpListener,
     pin::Pin,
-    sync::{
-        mpsc::{channel, Sender},
-        Arc, Mutex,
-    },
+    sync::{Arc, Mutex},
     task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
     thread::{self, JoinHandle},
     time::{Duration, Instant},
@@ -12,7 +13,7 @@ use std::{
 
 fn main() {
     let start = Instant::now();
-    let reactor = Reactor::new();
+    let reactor = Reactor::new(logger::from_env());
     let reactor = Arc::new(Mutex::new(reactor));
     let future1 = Task::new(reactor.clone(), 1, 1);
     let future2 = Task::new(reactor.clone(), 2, 2);
@@ -38,20 +39,20 @@ fn main() {
 
     block_on(mainfut);
     reactor.lock().map(|mut r| r.close()).unwrap();
+    reactor.lock().map(|r| r.log_poll_stats()).unwrap();
 }
 // ============================= EXECUTOR ====================================
 fn block_on<F: Future>(mut future: F) -> F::Output {
     let mywaker = Arc::new(MyWaker{thread: thread::current()});
     let waker = waker_into_waker(Arc::into_raw(mywaker));
     let mut cx = Context::from_waker(&waker);
-    let val = loop {
+    loop {
         let pinned = unsafe {Pin::new_unchecked(&mut future)};
         match Future::poll(pinned, &mut cx) {
             Poll::Ready(val) => break val,
             Poll::Pending => thread::park(),
         };
-    };
-    val
+    }
 }
 
 fn spawn<F: Future>(future: F) -> Pin<Box<F>> {
@@ -131,74 +132,112 @@ impl Future for Task {
 
 // =============================== REACTOR ===================================
 struct Reactor {
-    dispatcher: Sender<Event>,
     handle: Option<JoinHandle<()>>,
     readylist: Arc<Mutex<Vec<usize>>>,
-}
-
-#[derive(Debug)]
-enum Event {
-    Close,
-    Timeout(Waker, u64, usize),
+    wakers: Arc<Mutex<HashMap<usize, Waker>>>,
+    registrator: minimio::Registrator,
+    // The sockets the poll queue waits on, kept open until the reactor closes
+    streams: Vec<minimio::TcpStream>,
+    timers: Vec<JoinHandle<()>>,
+    logger: Arc<dyn Logger>,
 }
 
 impl Reactor {
-    fn new() -> Self {
-        let (tx, rx) = channel::<Event>();
+    fn new(logger: Arc<dyn Logger>) -> Self {
         let readylist = Arc::new(Mutex::new(vec![]));
         let rl_clone = readylist.clone();
-        let mut handles = vec![];
+        let wakers: Arc<Mutex<HashMap<usize, Waker>>> = Arc::new(Mutex::new(HashMap::new()));
+        let wakers_clone = wakers.clone();
+        let mut poll = minimio::Poll::new().expect("Error creating poll queue");
+        poll.enable_stats();
+        let registrator = poll.registrator();
+        let reactor_logger = logger.clone();
+
         let handle = thread::spawn(move || {
-            // This simulates some I/O resource
-            for event in rx {
-                println!("REACTOR: {:?}", event);
-                let rl_clone = rl_clone.clone();
-                match event {
-                    Event::Close => break,
-                    Event::Timeout(waker, duration, id) => {
-                        let event_handle = thread::spawn(move || {
-                            thread::sleep(Duration::from_secs(duration));
+            let mut events = minimio::Events::with_capacity(1024);
+            loop {
+                match poll.poll(&mut events, None) {
+                    Ok(_) => {
+                        for event in events.iter() {
+                            let id = event.id();
+                            let record = Record::new(Level::Debug, "reactor", "event is ready")
+                                .callback_id(id);
+                            logger::log(&*reactor_logger, record);
                             rl_clone.lock().map(|mut rl| rl.push(id)).unwrap();
-                            waker.wake();
-                        });
-
-                        handles.push(event_handle);
+                            if let Some(waker) = wakers_clone.lock().unwrap().remove(&id) {
+                                waker.wake();
+                            }
+                        }
                     }
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => break,
+                    Err(e) => panic!("Poll error: {:?}", e),
                 }
             }
-
-            for handle in handles {
-                handle.join().unwrap();
-            }
         });
 
         Reactor {
             readylist,
-            dispatcher: tx,
+            wakers,
             handle: Some(handle),
+            registrator,
+            streams: vec![],
+            timers: vec![],
+            logger,
         }
     }
 
-    fn register(&mut self, duration: u64, waker: Waker, data: usize) {
-        self.dispatcher
-            .send(Event::Timeout(waker, duration, data))
-            .unwrap();
+    /// Makes the event `id` ready after `duration` seconds. This simulates
+    /// some I/O resource: a timer thread writes to a local socket, and the
+    /// poll queue waits until it is readable.
+    fn register(&mut self, duration: u64, waker: Waker, id: usize) {
+        let record = Record::new(Level::Debug, "reactor", "registered timeout").callback_id(id);
+        logger::log(&*self.logger, record);
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Error binding timer socket");
+        let addr = listener.local_addr().unwrap();
+        let stream = minimio::TcpStream::connect(addr).expect("Error connecting timer socket");
+        let (mut timer_end, _) = listener.accept().expect("Error accepting timer socket");
+
+        self.wakers.lock().unwrap().insert(id, waker);
+        self.registrator
+            .register(&stream, id, minimio::Interests::READABLE)
+            .expect("Error registering timer socket");
+        self.streams.push(stream);
+
+        self.timers.push(thread::spawn(move || {
+            thread::sleep(Duration::from_secs(duration));
+            timer_end.write_all(&[1]).expect("Error firing timer");
+        }));
     }
 
     fn close(&mut self) {
-        self.dispatcher.send(Event::Close).unwrap();
+        self.registrator
+            .close_loop()
+            .expect("Error closing poll queue");
     }
 
     fn is_ready(&self, id_to_check: usize) -> bool {
         self.readylist
             .lock()
-            .map(|rl| rl.iter().any(|id| *id == id_to_check))
+            .map(|rl| rl.contains(&id_to_check))
             .unwrap()
     }
+
+    fn poll_stats(&self) -> minimio::PollStats {
+        self.registrator.stats()
+    }
+
+    fn log_poll_stats(&self) {
+        let stats = format!("poll stats: {}", self.poll_stats());
+        logger::log(&*self.logger, Record::new(Level::Info, "reactor", &stats));
+    }
 }
 
 impl Drop for Reactor {
     fn drop(&mut self) {
         self.handle.take().map(|h| h.join().unwrap()).unwrap();
+        for timer in self.timers.drain(..) {
+            timer.join().unwrap();
+        }
     }
 }
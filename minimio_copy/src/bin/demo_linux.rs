@@ -9,7 +9,7 @@ pub mod demo_linux {
 
         let queue = unsafe { ffi::epoll_create(1) };
         if queue < 0 {
-            panic!(io::Error::last_os_error());
+            panic!("{}", io::Error::last_os_error());
         }
         let mut streams = vec![];
         for i in 0..5 {
@@ -31,8 +31,8 @@ pub mod demo_linux {
             };
             let opt = ffi::EPOLL_CTL_ADD;
             let res = unsafe { ffi::epoll_ctl(queue, opt, stream.as_raw_fd(), &mut event) };
-            if queue < 0 {
-                panic!(io::Error::last_os_error());
+            if res < 0 {
+                panic!("{}", io::Error::last_os_error());
             }
             streams.push(stream);
             event_counter += 1;
@@ -41,8 +41,8 @@ pub mod demo_linux {
             let mut events = Vec::with_capacity(10);
             let res = unsafe { ffi::epoll_wait(queue, events.as_mut_ptr(), 10, -1) };
             println!("res = {}", res);
-            if queue < 0 {
-                panic!(io::Error::last_os_error());
+            if res < 0 {
+                panic!("{}", io::Error::last_os_error());
             }
 
             unsafe { events.set_len(res as usize) };
@@ -52,8 +52,8 @@ pub mod demo_linux {
             }
         }
         let res = unsafe { ffi::close(queue) };
-        if queue < 0 {
-            panic!(io::Error::last_os_error());
+        if res < 0 {
+            panic!("{}", io::Error::last_os_error());
         }
         println!("FINISHE");
     }
@@ -1,8 +1,13 @@
 use std::io;
 use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
+    atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::Instant;
+
+mod stats;
+pub use stats::PollStats;
+use stats::PollCounters;
 
 #[cfg(target_os = "windows")]
 mod windows;
@@ -35,6 +40,8 @@ pub type Token = usize;
 pub struct Poll {
     registry: Registry,
     is_poll_dead: Arc<AtomicBool>,
+    counters: Arc<PollCounters>,
+    last_return: Option<Instant>,
 }
 
 impl Poll {
@@ -42,13 +49,28 @@ impl Poll {
         Selector::new().map(|selector| Poll {
             registry: Registry { selector },
             is_poll_dead: Arc::new(AtomicBool::new(false)),
+            counters: Arc::new(PollCounters::default()),
+            last_return: None,
         })
     }
 
     pub fn registrator(&self) -> Registrator {
         self.registry
             .selector
-            .registrator(self.is_poll_dead.clone())
+            .registrator(self.is_poll_dead.clone(), self.counters.clone())
+    }
+
+    /// Starts collecting statistics about this event queue. The counters are
+    /// shared with every `Registrator` so they can be read from other threads
+    /// through `Registrator::stats`.
+    pub fn enable_stats(&self) {
+        self.counters.enable();
+    }
+
+    /// Returns a snapshot of the statistics collected so far. All counters stay
+    /// at zero unless `enable_stats` has been called.
+    pub fn stats(&self) -> PollStats {
+        self.counters.snapshot()
     }
 
     /// Polls the event loop. The thread yields to the OS while witing for either
@@ -57,11 +79,21 @@ impl Poll {
     pub fn poll(&mut self, events: &mut Events, timeout_ms: Option<i32>) -> io::Result<usize> {
         // A negative timout is converted to a 0 timeout
         let timeout = timeout_ms.map(|n| if n < 0 { 0 } else { n });
+        let stats_enabled = self.counters.is_enabled();
+        let started = Instant::now();
+        if let (true, Some(last_return)) = (stats_enabled, self.last_return) {
+            self.counters.record_processing(started - last_return);
+        }
+
         loop {
             let res = self.registry.selector.select(events, timeout);
             match res {
                 Ok(()) => break,
-                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                    if stats_enabled {
+                        self.counters.record_spurious_wakeup();
+                    }
+                }
                 Err(e) => return Err(e),
             };
         }
@@ -70,6 +102,16 @@ impl Poll {
             return Err(io::Error::new(io::ErrorKind::Interrupted, "Poll closed."));
         }
 
+        self.counters.record_delivered(events.len());
+        if stats_enabled {
+            let returned = Instant::now();
+            self.counters.record_poll(events.len(), returned - started);
+            if events.is_empty() && timeout.is_none() {
+                self.counters.record_spurious_wakeup();
+            }
+            self.last_return = Some(returned);
+        }
+
         Ok(events.len())
     }
 }
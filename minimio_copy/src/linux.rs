@@ -1,3 +1,4 @@
+use crate::stats::{PollCounters, PollStats};
 use crate::{Events, Interests, Token};
 use std::io::{self, IoSliceMut, Read, Write};
 use std::net;
@@ -10,6 +11,7 @@ use std::sync::{
 pub struct Registrator {
     fd: RawFd,
     is_poll_dead: Arc<AtomicBool>,
+    counters: Arc<PollCounters>,
 }
 
 impl Registrator {
@@ -43,13 +45,21 @@ impl Registrator {
             unimplemented!();
         }
 
+        self.counters.record_registration();
         Ok(())
     }
 
+    /// Returns a snapshot of the statistics of the `Poll` this registrator
+    /// belongs to.
+    pub fn stats(&self) -> PollStats {
+        self.counters.snapshot()
+    }
+
     pub fn close_loop(&self) -> io::Result<()> {
         if self
             .is_poll_dead
-            .compare_and_swap(false, true, Ordering::SeqCst)
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
         {
             return Err(io::Error::new(
                 io::ErrorKind::Interrupted,
@@ -85,10 +95,15 @@ impl Selector {
         })
     }
 
-    pub fn registrator(&self, is_poll_dead: Arc<AtomicBool>) -> Registrator {
+    pub(crate) fn registrator(
+        &self,
+        is_poll_dead: Arc<AtomicBool>,
+        counters: Arc<PollCounters>,
+    ) -> Registrator {
         Registrator {
             fd: self.fd,
             is_poll_dead,
+            counters,
         }
     }
 }
@@ -99,7 +114,7 @@ impl Drop for Selector {
             Ok(..) => (),
             Err(e) => {
                 if !std::thread::panicking() {
-                    panic!(e);
+                    panic!("{}", e);
                 }
             }
         }
@@ -153,10 +168,8 @@ impl AsRawFd for TcpStream {
 }
 
 mod ffi {
-    use std::io;
-    use std::os::raw::c_void;
-
     pub const EPOLL_CTL_ADD: i32 = 1;
+    #[allow(dead_code)]
     pub const EPOLL_CTL_DEL: i32 = 2;
     pub const EPOLLIN: i32 = 0x1;
     pub const EPOLLONESHOT: i32 = 0x40000000;
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Counters shared between a `Poll` and every `Registrator` created from it.
+///
+/// Nothing is recorded until `Poll::enable_stats` has been called, so a poll
+/// instance that nobody is watching only pays for a single atomic load per call.
+/// The one exception is the number of active registrations, which is tracked
+/// from the start so it stays correct when stats are enabled later on.
+#[derive(Debug, Default)]
+pub(crate) struct PollCounters {
+    enabled: AtomicBool,
+    polls: AtomicUsize,
+    events: AtomicUsize,
+    max_events_per_poll: AtomicUsize,
+    spurious_wakeups: AtomicUsize,
+    blocked_nanos: AtomicU64,
+    processing_nanos: AtomicU64,
+    registrations: AtomicUsize,
+}
+
+impl PollCounters {
+    pub(crate) fn enable(&self) {
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Records one finished call to `select` which returned `n_events` events
+    /// after blocking for `blocked`.
+    pub(crate) fn record_poll(&self, n_events: usize, blocked: Duration) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.events.fetch_add(n_events, Ordering::Relaxed);
+        self.max_events_per_poll
+            .fetch_max(n_events, Ordering::Relaxed);
+        self.blocked_nanos
+            .fetch_add(blocked.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Records that `n_events` events were delivered. Called on every poll,
+    /// whether stats are enabled or not.
+    pub(crate) fn record_delivered(&self, n_events: usize) {
+        // All our registrations are oneshot, so a delivered event means the
+        // registration is no longer active.
+        let _ = self
+            .registrations
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                Some(n.saturating_sub(n_events))
+            });
+    }
+
+    pub(crate) fn record_spurious_wakeup(&self) {
+        self.spurious_wakeups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the time the caller spent between two calls to `poll`.
+    pub(crate) fn record_processing(&self, processing: Duration) {
+        self.processing_nanos
+            .fetch_add(processing.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Called on every registration, whether stats are enabled or not.
+    pub(crate) fn record_registration(&self) {
+        self.registrations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> PollStats {
+        if !self.is_enabled() {
+            return PollStats::default();
+        }
+
+        PollStats {
+            polls: self.polls.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+            max_events_per_poll: self.max_events_per_poll.load(Ordering::Relaxed),
+            spurious_wakeups: self.spurious_wakeups.load(Ordering::Relaxed),
+            time_blocked: Duration::from_nanos(self.blocked_nanos.load(Ordering::Relaxed)),
+            time_processing: Duration::from_nanos(self.processing_nanos.load(Ordering::Relaxed)),
+            registrations: self.registrations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A point in time snapshot of what a `Poll` instance has been doing. Obtained
+/// by calling `Poll::stats` or `Registrator::stats`.
+///
+/// A wakeup is counted as spurious if the OS interrupted the wait, or if the
+/// poll returned without any events even though no timeout was given.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PollStats {
+    /// Number of completed calls to `Poll::poll`.
+    pub polls: usize,
+    /// Total number of events returned over all polls.
+    pub events: usize,
+    /// The largest number of events returned by a single poll.
+    pub max_events_per_poll: usize,
+    /// Number of wakeups that returned no events before the timeout expired.
+    pub spurious_wakeups: usize,
+    /// Time spent blocked in the OS waiting for events.
+    pub time_blocked: Duration,
+    /// Time spent by the caller between two polls, processing the events.
+    pub time_processing: Duration,
+    /// Registrations which have not delivered their event yet.
+    pub registrations: usize,
+}
+
+impl PollStats {
+    /// Average number of events returned per poll.
+    pub fn events_per_poll(&self) -> f64 {
+        if self.polls == 0 {
+            0.0
+        } else {
+            self.events as f64 / self.polls as f64
+        }
+    }
+}
+
+impl fmt::Display for PollStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "polls: {}, events: {} ({:.2}/poll, max {}), spurious wakeups: {}, \
+             blocked: {:?}, processing: {:?}, registrations: {}",
+            self.polls,
+            self.events,
+            self.events_per_poll(),
+            self.max_events_per_poll,
+            self.spurious_wakeups,
+            self.time_blocked,
+            self.time_processing,
+            self.registrations
+        )
+    }
+}
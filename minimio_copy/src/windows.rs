@@ -1,6 +1,7 @@
 #![allow(non_camel_case_types)]
 #![allow(dead_code)]
 
+use crate::stats::{PollCounters, PollStats};
 use crate::{Interests, Token};
 use std::collections::LinkedList;
 use std::io::{self, Read, Write};
@@ -102,6 +103,7 @@ impl AsRawSocket for TcpStream {
 pub struct Registrator {
     completion_port: isize,
     is_poll_dead: Arc<AtomicBool>,
+    counters: Arc<PollCounters>,
 }
 
 impl Registrator {
@@ -133,9 +135,16 @@ impl Registrator {
             unimplemented!();
         }
 
+        self.counters.record_registration();
         Ok(())
     }
 
+    /// Returns a snapshot of the statistics of the `Poll` this registrator
+    /// belongs to.
+    pub fn stats(&self) -> PollStats {
+        self.counters.snapshot()
+    }
+
     pub fn close_loop(&self) -> io::Result<()> {
         if self
             .is_poll_dead
@@ -166,10 +175,15 @@ impl Selector {
         Ok(Selector { completion_port })
     }
 
-    pub fn registrator(&self, is_poll_dead: Arc<AtomicBool>) -> Registrator {
+    pub(crate) fn registrator(
+        &self,
+        is_poll_dead: Arc<AtomicBool>,
+        counters: Arc<PollCounters>,
+    ) -> Registrator {
         Registrator {
             completion_port: self.completion_port,
             is_poll_dead,
+            counters,
         }
     }
 
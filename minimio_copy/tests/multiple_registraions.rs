@@ -1,4 +1,7 @@
-use minimio::{Events, Interests, Poll, TcpStream};
+// `&mut` is needed by the windows `Registrator`
+#![allow(clippy::unnecessary_mut_passed)]
+
+use minimio::{Interests, Poll, TcpStream};
 use std::io::{self, Read, Write};
 use std::sync::mpsc::channel;
 use std::thread;
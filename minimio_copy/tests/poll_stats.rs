@@ -0,0 +1,56 @@
+use minimio::{Events, Interests, Poll, PollStats, TcpStream};
+use std::io::Write;
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn poll_stats_disabled_by_default() {
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(16);
+
+    poll.poll(&mut events, Some(0)).unwrap();
+    assert_eq!(poll.stats(), PollStats::default());
+}
+
+#[test]
+fn poll_stats_count_polls_and_blocking_time() {
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    poll.enable_stats();
+    let mut events = Events::with_capacity(16);
+
+    poll.poll(&mut events, Some(20)).unwrap();
+    poll.poll(&mut events, Some(0)).unwrap();
+
+    let stats = registrator.stats();
+    assert_eq!(stats, poll.stats());
+    assert_eq!(stats.polls, 2);
+    assert_eq!(stats.events, 0);
+    assert_eq!(stats.max_events_per_poll, 0);
+    assert_eq!(stats.spurious_wakeups, 0);
+    assert_eq!(stats.registrations, 0);
+    assert!(stats.time_blocked >= Duration::from_millis(20));
+    assert_eq!(stats.events_per_poll(), 0.0);
+}
+
+#[test]
+fn poll_stats_count_registrations_made_before_enabling() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut poll = Poll::new().unwrap();
+    let registrator = poll.registrator();
+    registrator
+        .register(&stream, 1, Interests::READABLE)
+        .unwrap();
+
+    poll.enable_stats();
+    assert_eq!(poll.stats().registrations, 1);
+
+    let (mut accepted, _) = listener.accept().unwrap();
+    accepted.write_all(b"x").unwrap();
+    let mut events = Events::with_capacity(16);
+    poll.poll(&mut events, Some(1000)).unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(poll.stats().registrations, 0);
+}
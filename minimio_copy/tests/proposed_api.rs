@@ -1,3 +1,6 @@
+// `&mut` is needed by the windows `Registrator`
+#![allow(clippy::unnecessary_mut_passed)]
+
 use minimio::{Events, Interests, Poll, Registrator, TcpStream};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::{io, io::Read, io::Write, thread, thread::JoinHandle};